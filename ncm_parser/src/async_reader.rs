@@ -0,0 +1,205 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+use crate::header::HeaderWalk;
+use crate::ParseError::{self, *};
+use crate::{
+    check_read_result, ImageFormat, MetadataKind, NCMFile, NCMHeader, NCMKey,
+    Segment,
+};
+#[cfg(feature = "serde")]
+use crate::{DJMetadata, NCMMetadata};
+
+/// Parse the ncm file with async reader. The async version of
+/// [`from_reader()`](crate::from_reader()).
+/// # Example
+/// ```no_run
+/// # async fn example() {
+/// let file = tokio::fs::File::open("xxx.ncm").await.unwrap();
+/// let parsed_ncm_file = ncm_parser::from_async_reader(file).await.unwrap();
+/// # }
+/// ```
+pub async fn from_async_reader<R>(mut reader: R) -> Result<NCMFile, ParseError>
+where
+    R: AsyncRead + Unpin,
+{
+    let header = parse_header_async(&mut reader).await?;
+    let mut music = Vec::new();
+    if let Err(source) = reader.read_to_end(&mut music).await {
+        return Err(Io {
+            segment: Segment::Music,
+            offset: header.music_offset() + music.len() as u64,
+            source,
+        });
+    }
+    Ok(NCMFile {
+        is_decrypted_flags: 0,
+        metadata_kind: MetadataKind::None,
+        rc4_key: header.rc4_key,
+        metadata: header.metadata,
+        decrypted_metadata: Vec::new(),
+        crc32: header.crc32,
+        image_frame_len: header.image_frame_len,
+        image: header.image,
+        music,
+    })
+}
+
+/// Parse everything in front of the music data with async reader.
+/// The async version of [`parse_header()`](crate::parse_header()).
+pub async fn parse_header_async<R>(mut reader: R) -> Result<NCMHeader, ParseError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut walk = HeaderWalk::new(false);
+    while let Some((segment, len)) = walk.next_read() {
+        let data =
+            read_exact_async(&mut reader, len, segment, &mut walk.offset).await?;
+        walk.feed(data)?;
+    }
+    Ok(walk.header)
+}
+
+/// The async version of [`read_exact_reader()`](crate::read_exact_reader()).
+async fn read_exact_async<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    len: u64,
+    segment: Segment,
+    offset: &mut u64,
+) -> Result<Vec<u8>, ParseError> {
+    let mut data = Vec::new();
+    let result = (&mut *reader).take(len).read_to_end(&mut data).await;
+    check_read_result(result, &data, len, segment, offset)?;
+    Ok(data)
+}
+
+/// A streaming ncm parser for async readers, which decrypts the music data
+/// on the fly through [`AsyncRead`]. The async version of
+/// [`NCMReader`](crate::NCMReader).
+///
+/// There is no `decrypt_range()` like [`NCMReader`](crate::NCMReader), as
+/// tokio's `AsyncSeek` is not supported here. To decrypt a range of the
+/// music data, seek the underlying reader to
+/// [`music_offset()`](NCMHeader::music_offset()) plus the position, then
+/// decrypt the bytes read with [`get_key()`](AsyncNCMReader::get_key())
+/// and [`decrypt_music_in_place()`](crate::decrypt_music_in_place()).
+/// # Example
+/// ```no_run
+/// # async fn example() {
+/// let file = tokio::fs::File::open("xxx.ncm").await.unwrap();
+/// let mut reader = ncm_parser::AsyncNCMReader::new(file).await.unwrap();
+/// let mut music = tokio::fs::File::create("xxx.flac").await.unwrap();
+/// tokio::io::copy(&mut reader, &mut music).await.unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncNCMReader<R> {
+    inner: R,
+    key: NCMKey,
+    pos: u64,
+    header: NCMHeader,
+}
+
+impl<R: AsyncRead + Unpin> AsyncNCMReader<R> {
+    /// Parse everything in front of the music data and decrypt the RC4 key.
+    /// The reader is left at the beginning of the music data.
+    pub async fn new(mut reader: R) -> Result<Self, ParseError> {
+        let header = parse_header_async(&mut reader).await?;
+        Ok(Self {
+            inner: reader,
+            key: NCMKey::new(&header.rc4_key)?,
+            pos: 0,
+            header,
+        })
+    }
+}
+
+impl<R> AsyncNCMReader<R> {
+    /// Get everything parsed in front of the music data.
+    pub fn get_header(&self) -> &NCMHeader {
+        &self.header
+    }
+
+    /// Get cover image. Usually in PNG or JPEG format.
+    pub fn get_image(&self) -> &Vec<u8> {
+        self.header.get_image()
+    }
+
+    /// Detect the format of the cover image from its first bytes. Returns
+    /// `None` if the file has no cover image or the format is unknown.
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        self.header.image_format()
+    }
+
+    /// Get the decrypted RC4 key, which can be used to decrypt the music with
+    /// [`decrypt_music_in_place()`](crate::decrypt_music_in_place()).
+    pub fn get_key(&self) -> &NCMKey {
+        &self.key
+    }
+
+    /// Get decrypted metadata.
+    pub fn get_metadata(&self) -> Result<Vec<u8>, ParseError> {
+        self.header.get_metadata()
+    }
+
+    /// Get the kind of the metadata.
+    pub fn get_metadata_kind(&self) -> Result<MetadataKind, ParseError> {
+        self.header.get_metadata_kind()
+    }
+
+    /// Directly get metadata, which is not decrypted.
+    pub fn get_metadata_unchecked(&self) -> &Vec<u8> {
+        self.header.get_metadata_unchecked()
+    }
+
+    #[cfg(feature = "serde")]
+    /// Parse the JSON format metadata into struct.
+    /// For radio or podcast programs, the song in `mainMusic` is returned.
+    pub fn get_parsed_metadata(&self) -> Result<NCMMetadata, ParseError> {
+        self.header.get_parsed_metadata()
+    }
+
+    #[cfg(feature = "serde")]
+    /// Parse the JSON format metadata of radio or podcast programs into struct.
+    pub fn get_parsed_dj_metadata(&self) -> Result<DJMetadata, ParseError> {
+        self.header.get_parsed_dj_metadata()
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// Reading from the underlying reader directly would mess up the
+    /// decryption of the rest music data.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `AsyncNCMReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncNCMReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+
+        let music = &mut buf.filled_mut()[filled..];
+        this.key.apply(music, this.pos);
+        this.pos += music.len() as u64;
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,197 @@
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use aes::Aes128Enc;
+use base64::engine::general_purpose::STANDARD as base64enc;
+use base64::Engine;
+use cipher::block_padding::Pkcs7;
+use cipher::{BlockEncrypt, KeyInit};
+
+#[cfg(feature = "serde")]
+use crate::{DJMetadata, NCMMetadata};
+use crate::{NCMKey, CORE_KEY, META_KEY};
+
+/// A builder to create an ncm file from music, metadata and cover image.
+/// The file built can be parsed by [`from_iter()`](crate::from_iter()),
+/// [`from_reader()`](crate::from_reader()) and [`NCMReader`](crate::NCMReader)
+/// into exactly the same data.
+/// # Example
+/// ```no_run
+/// let music = std::fs::read("xxx.flac").unwrap();
+/// let metadata = br#"{"musicName":"xxx","format":"flac"}"#.to_vec();
+///
+/// let ncm_file = ncm_parser::NCMBuilder::new(music, b"123456".to_vec())
+///     .raw_metadata(metadata)
+///     .image(std::fs::read("xxx.jpg").unwrap())
+///     .build();
+/// std::fs::write("xxx.ncm", ncm_file).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct NCMBuilder {
+    rc4_key: Vec<u8>,
+    metadata: Vec<u8>,
+    metadata_prefix: &'static [u8],
+    image: Vec<u8>,
+    image_frame_len: u32,
+    crc32: u32,
+    music: Vec<u8>,
+}
+
+impl NCMBuilder {
+    /// Create a builder with the music (usually in MP3 or FLAC format) and
+    /// the RC4 key used to encrypt it. The RC4 key should not contain the
+    /// `neteasecloudmusic` prefix.
+    pub fn new(music: Vec<u8>, rc4_key: Vec<u8>) -> Self {
+        Self {
+            rc4_key,
+            metadata: Vec::new(),
+            metadata_prefix: b"music:",
+            image: Vec::new(),
+            image_frame_len: 0,
+            crc32: 0,
+            music,
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    /// Set the metadata. It will be stored in JSON format.
+    pub fn metadata(self, metadata: &NCMMetadata) -> Self {
+        let metadata = serde_json::to_vec(metadata)
+            .expect("NCMMetadata should always be serializable.");
+        self.raw_metadata(metadata)
+    }
+
+    /// Set the JSON format metadata, which is exactly what
+    /// [`NCMFile::get_metadata()`](crate::NCMFile::get_metadata()) returns.
+    /// The metadata segment is left empty if it is never set.
+    pub fn raw_metadata(mut self, metadata: Vec<u8>) -> Self {
+        self.metadata = metadata;
+        self.metadata_prefix = b"music:";
+        self
+    }
+
+    #[cfg(feature = "serde")]
+    /// Set the metadata of a radio or podcast program instead.
+    /// It will be stored in JSON format.
+    pub fn dj_metadata(self, metadata: &DJMetadata) -> Self {
+        let metadata = serde_json::to_vec(metadata)
+            .expect("DJMetadata should always be serializable.");
+        self.raw_dj_metadata(metadata)
+    }
+
+    /// Set the JSON format metadata of a radio or podcast program instead,
+    /// which is stored with the `dj:` prefix rather than `music:`.
+    pub fn raw_dj_metadata(mut self, metadata: Vec<u8>) -> Self {
+        self.metadata = metadata;
+        self.metadata_prefix = b"dj:";
+        self
+    }
+
+    /// Set the cover image. Usually in PNG or JPEG format.
+    /// The image segment is left empty if it is never set.
+    pub fn image(mut self, image: Vec<u8>) -> Self {
+        self.image = image;
+        self
+    }
+
+    /// Reserve at least `len` bytes for the cover image like newer clients do.
+    /// The space not used by the image is filled with zero.
+    pub fn image_frame_len(mut self, len: u32) -> Self {
+        self.image_frame_len = len;
+        self
+    }
+
+    /// Set the CRC32 stored in front of the image segment, which is zero if
+    /// it is never set. What the clients calculate it from is unknown, so
+    /// it is stored as is.
+    pub fn crc32(mut self, crc32: u32) -> Self {
+        self.crc32 = crc32;
+        self
+    }
+
+    /// Build the ncm file and store it in [Vec].
+    pub fn build(&self) -> Vec<u8> {
+        let mut ncm_file = Vec::with_capacity(self.music.len() + self.image.len());
+        let Ok(()) = self.write_with(|buf| -> Result<(), !> {
+            ncm_file.extend_from_slice(buf);
+            Ok(())
+        });
+        ncm_file
+    }
+
+    #[cfg(feature = "std")]
+    /// Build the ncm file and write it into `writer`.
+    /// The music is encrypted chunk by chunk, so it is never copied as a whole.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.write_with(|buf| writer.write_all(buf))
+    }
+
+    /// Build the ncm file and pass it to `write_all` piece by piece, so that
+    /// it works without [`std::io::Write`].
+    fn write_with<E, F>(&self, mut write_all: F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        write_all(b"CTENFDAM")?;
+        write_all(&[0; 2])?;
+
+        let mut rc4_key = b"neteasecloudmusic".to_vec();
+        rc4_key.extend_from_slice(&self.rc4_key);
+        let rc4_key = encrypt_aes(CORE_KEY, &rc4_key);
+        write_segment(&mut write_all, &rc4_key, 0x64)?;
+
+        if self.metadata.is_empty() {
+            write_segment(&mut write_all, &[], 0x63)?;
+        } else {
+            let mut metadata = self.metadata_prefix.to_vec();
+            metadata.extend_from_slice(&self.metadata);
+            let metadata = base64enc.encode(encrypt_aes(META_KEY, &metadata));
+            let mut metadata = metadata.into_bytes();
+            metadata.splice(0..0, *b"163 key(Don't modify):");
+            write_segment(&mut write_all, &metadata, 0x63)?;
+        }
+
+        // CRC32, an unused byte and the size of the image frame.
+        let image_frame_len = self.image_frame_len.max(self.image.len() as u32);
+        write_all(&self.crc32.to_le_bytes())?;
+        write_all(&[0])?;
+        write_all(&image_frame_len.to_le_bytes())?;
+        write_segment(&mut write_all, &self.image, 0)?;
+        let padding = image_frame_len as usize - self.image.len();
+        write_all(&vec![0; padding])?;
+
+        let key = NCMKey::from_rc4_key(&self.rc4_key);
+        let mut buf = [0; 0x8000];
+        let mut offset = 0;
+        for chunk in self.music.chunks(buf.len()) {
+            let buf = &mut buf[..chunk.len()];
+            buf.copy_from_slice(chunk);
+            key.apply(buf, offset);
+            write_all(buf)?;
+            offset += chunk.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Encrypt `data` with AES-128 in ECB mode and PKCS#7 padding.
+fn encrypt_aes(key: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    let mut buf = data.to_vec();
+    buf.resize((data.len() / 16 + 1) * 16, 0);
+    Aes128Enc::new(key.into())
+        .encrypt_padded::<Pkcs7>(&mut buf, data.len())
+        .expect("The buffer should be large enough for padding.");
+    buf
+}
+
+/// The inverse function of [`read_segment_iter()`](crate::read_segment_iter()).
+fn write_segment<E, F>(write_all: &mut F, data: &[u8], salt: u8) -> Result<(), E>
+where
+    F: FnMut(&[u8]) -> Result<(), E>,
+{
+    write_all(&(data.len() as u32).to_le_bytes())?;
+    let data: Vec<u8> = data.iter().map(|x| x ^ salt).collect();
+    write_all(&data)
+}
@@ -0,0 +1,141 @@
+#[cfg(feature = "serde")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_json::{Map, Value};
+
+#[cfg(feature = "serde")]
+use crate::metadata::{string_or_number, u64_or_string_or_default};
+#[cfg(feature = "serde")]
+use crate::ParseError::{self, *};
+
+/// Every byte of the cache file is XORed with it. The cache files are kept
+/// by the NeteaseCloudMusic clients for streamed songs, usually named
+/// `{music_id}-{bitrate}-{md5}.uc` or `.uc!`.
+pub const CACHE_KEY: u8 = 0xa3;
+
+/// Decrypt (or encrypt) the cache file data in `buf` in place.
+/// Every byte is decrypted the same way, so it works on any part of the file.
+pub fn decrypt_cache_in_place(buf: &mut [u8]) {
+    buf.iter_mut().for_each(|x| *x ^= CACHE_KEY);
+}
+
+/// A reader decrypts the cache file on the fly through [`Read`].
+/// The decrypted data is usually in MP3 or FLAC format.
+/// # Example
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut reader = ncm_parser::CacheReader::new(File::open("xxx.uc").unwrap());
+/// let mut music = File::create("xxx.mp3").unwrap();
+/// std::io::copy(&mut reader, &mut music).unwrap();
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct CacheReader<R> {
+    inner: R,
+}
+
+#[cfg(feature = "std")]
+impl<R> CacheReader<R> {
+    /// Wrap the reader of a cache file. Nothing is read until asked.
+    pub fn new(reader: R) -> Self {
+        Self { inner: reader }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `CacheReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for CacheReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        decrypt_cache_in_place(&mut buf[..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Seek> Seek for CacheReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The information of a cache file, read from the JSON format `.idx!` or
+/// `.info` file next to it, or from the name of the cache file.
+///
+/// Different clients use different field names, and the missing ones are
+/// left empty or zero.
+pub struct CacheInfo {
+    /// music_id might not be a number.
+    #[serde(
+        default,
+        alias = "id",
+        alias = "songId",
+        deserialize_with = "string_or_number"
+    )]
+    pub music_id: String,
+    /// Zero if missing or invalid.
+    #[serde(default, alias = "br", deserialize_with = "u64_or_string_or_default")]
+    pub bitrate: u64,
+    /// The size of the whole song, which may be larger than the cache file
+    /// if the song is not fully cached. Zero if missing or invalid.
+    #[serde(
+        default,
+        alias = "filesize",
+        deserialize_with = "u64_or_string_or_default"
+    )]
+    pub size: u64,
+    /// The MD5 of the whole song.
+    #[serde(default, alias = "filemd5")]
+    pub md5: Option<String>,
+    /// All the other fields not listed above.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[cfg(feature = "serde")]
+impl CacheInfo {
+    /// Parse the content of the `.idx!` or `.info` file.
+    pub fn new(info: &[u8]) -> Result<Self, ParseError> {
+        serde_json::from_slice(info)
+            .map_err(|err| ParseMetadataFailed(err.to_string()))
+    }
+
+    /// Read the music id, bitrate and MD5 from the name of the cache file
+    /// like `{music_id}-{bitrate}-{md5}.uc`, where the extension is optional.
+    /// Returns `None` if the name doesn't look like that.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let stem = file_name.split('.').next()?;
+        let mut parts = stem.split('-');
+        let music_id = parts.next().filter(|id| !id.is_empty())?;
+        let bitrate = parts.next()?.parse().ok()?;
+        Some(Self {
+            music_id: music_id.to_string(),
+            bitrate,
+            md5: parts.next().map(ToString::to_string),
+            ..Default::default()
+        })
+    }
+}
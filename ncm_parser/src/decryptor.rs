@@ -0,0 +1,451 @@
+use std::boxed::Box;
+use std::io::Read;
+use std::vec::Vec;
+
+#[cfg(feature = "serde")]
+use crate::{parse_song, NCMMetadata};
+use crate::{
+    CacheReader, KGMFile, KGMFormat, KGMMask, KGMReader, KWMFile, KWMReader,
+    MetadataKind, NCMFile, NCMReader, ParseError, QMCFile, QMCFormat, QMCReader,
+    KWM_MAGIC, KWM_MAGIC_OLD,
+};
+
+/// An encrypted music file opened by a [`Decryptor`].
+///
+/// Only [`music()`](EncryptedTrack::music()) is required, the formats
+/// without metadata or cover image can leave the others as they are.
+pub trait EncryptedTrack {
+    /// Get the decrypted music as a stream. It should be called only once.
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError>;
+
+    /// Get the decrypted metadata in JSON format, or `None` if the file
+    /// doesn't carry any.
+    fn metadata(&mut self) -> Result<Option<Vec<u8>>, ParseError> {
+        Ok(None)
+    }
+
+    /// Get the kind of the metadata. By default it is
+    /// [`Music`](MetadataKind::Music) if the file carries any.
+    fn metadata_kind(&mut self) -> Result<MetadataKind, ParseError> {
+        Ok(match self.metadata()? {
+            Some(_) => MetadataKind::Music,
+            None => MetadataKind::None,
+        })
+    }
+
+    /// Get the metadata as it is stored in the file, still encrypted, or
+    /// `None` if the file doesn't carry any. For ncm files it is the
+    /// `163 key(Don't modify):...` string used by the client to identify
+    /// the song.
+    fn raw_metadata(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Get the cover image, or `None` if the file doesn't carry any.
+    fn cover(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+#[cfg(feature = "serde")]
+/// Parse the metadata of any [`EncryptedTrack`], which is implemented for
+/// all of them.
+pub trait EncryptedTrackExt: EncryptedTrack {
+    /// Parse the metadata of the song, or `None` if the file doesn't carry
+    /// any. For radio or podcast programs, the song in `mainMusic` is
+    /// returned.
+    fn parsed_metadata(&mut self) -> Result<Option<NCMMetadata>, ParseError> {
+        let Some(metadata) = self.metadata()? else {
+            return Ok(None);
+        };
+        parse_song(self.metadata_kind()?, &metadata).map(Some)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: EncryptedTrack + ?Sized> EncryptedTrackExt for T {}
+
+/// The built-in formats, told apart by [`Decryptor::kind()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DecryptorKind {
+    /// [`NCMDecryptor`].
+    Ncm,
+    /// [`KGMDecryptor`].
+    Kgm,
+    /// [`KWMDecryptor`].
+    Kwm,
+    /// [`QMCDecryptor`].
+    Qmc,
+    /// [`CacheDecryptor`].
+    Cache,
+    /// Formats added outside of this crate.
+    Other,
+}
+
+/// An encrypted music format, which recognizes the files and opens them as
+/// [`EncryptedTrack`]s. Implement it to add new formats to a [`Registry`].
+pub trait Decryptor: Send + Sync {
+    /// A short name of the format, such as `ncm`.
+    fn name(&self) -> &'static str;
+
+    /// Which of the built-in formats it is. Formats added outside of this
+    /// crate should leave it as [`DecryptorKind::Other`].
+    fn kind(&self) -> DecryptorKind {
+        DecryptorKind::Other
+    }
+
+    /// Whether the file starts with the magic header of the format.
+    /// `magic` is the first 16 bytes of the file, or fewer if the file is
+    /// shorter than that.
+    fn probe_magic(&self, _magic: &[u8]) -> bool {
+        false
+    }
+
+    /// Whether the file extension (without the leading dot) belongs to the
+    /// format. Used when no format claims the magic header.
+    fn probe_extension(&self, extension: &str) -> bool;
+
+    /// Open the file from the beginning. `extension` is the same as the one
+    /// passed to [`probe_extension()`](Decryptor::probe_extension()), which
+    /// is empty if the file has none.
+    fn open<'a>(
+        &self,
+        reader: Box<dyn Read + 'a>,
+        extension: &str,
+    ) -> Result<Box<dyn EncryptedTrack + 'a>, ParseError>;
+}
+
+/// A list of [`Decryptor`]s to pick the format of the files.
+/// # Example
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::Read;
+///
+/// let registry = ncm_parser::Registry::new();
+/// let mut magic = [0; 16];
+/// File::open("xxx.kgm").unwrap().read_exact(&mut magic).unwrap();
+/// let decryptor = registry.find(&magic, "kgm").unwrap();
+///
+/// let file = File::open("xxx.kgm").unwrap();
+/// let mut track = decryptor.open(Box::new(file), "kgm").unwrap();
+/// let mut music = File::create("xxx.flac").unwrap();
+/// std::io::copy(&mut track.music().unwrap(), &mut music).unwrap();
+/// ```
+pub struct Registry {
+    decryptors: Vec<Box<dyn Decryptor>>,
+}
+
+impl Registry {
+    /// Create a registry with all the formats supported by this crate.
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register(NCMDecryptor)
+            .register(KGMDecryptor::default())
+            .register(KWMDecryptor)
+            .register(QMCDecryptor)
+            .register(CacheDecryptor);
+        registry
+    }
+
+    /// Create a registry without any format.
+    pub fn empty() -> Self {
+        Self {
+            decryptors: Vec::new(),
+        }
+    }
+
+    /// Add a format. The formats added earlier take precedence.
+    pub fn register<D: Decryptor + 'static>(&mut self, decryptor: D) -> &mut Self {
+        self.decryptors.push(Box::new(decryptor));
+        self
+    }
+
+    /// Find the format of a file. The magic header is checked against every
+    /// format first, then the extension.
+    pub fn find(&self, magic: &[u8], extension: &str) -> Option<&dyn Decryptor> {
+        self.iter()
+            .find(|decryptor| decryptor.probe_magic(magic))
+            .or_else(|| {
+                self.iter()
+                    .find(|decryptor| decryptor.probe_extension(extension))
+            })
+    }
+
+    /// Iterate over the formats in order.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Decryptor> {
+        self.decryptors.iter().map(|decryptor| decryptor.as_ref())
+    }
+}
+
+impl core::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|decryptor| decryptor.name()))
+            .finish()
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `.ncm` files, opened as [`NCMReader`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NCMDecryptor;
+
+impl Decryptor for NCMDecryptor {
+    fn name(&self) -> &'static str {
+        "ncm"
+    }
+
+    fn kind(&self) -> DecryptorKind {
+        DecryptorKind::Ncm
+    }
+
+    fn probe_magic(&self, magic: &[u8]) -> bool {
+        magic.starts_with(b"CTENFDAM")
+    }
+
+    fn probe_extension(&self, extension: &str) -> bool {
+        extension.eq_ignore_ascii_case("ncm")
+    }
+
+    fn open<'a>(
+        &self,
+        reader: Box<dyn Read + 'a>,
+        _extension: &str,
+    ) -> Result<Box<dyn EncryptedTrack + 'a>, ParseError> {
+        Ok(Box::new(NCMReader::new(reader)?))
+    }
+}
+
+/// `.kgm`, `.kgma` and `.vpr` files, opened as [`KGMReader`].
+#[derive(Debug, Clone, Default)]
+pub struct KGMDecryptor {
+    mask: Option<KGMMask>,
+}
+
+impl KGMDecryptor {
+    /// Open the older files encrypted with the mask table as well, which is
+    /// experimental, see [`KGMMask`].
+    pub fn with_mask(mask: KGMMask) -> Self {
+        Self { mask: Some(mask) }
+    }
+}
+
+impl Decryptor for KGMDecryptor {
+    fn name(&self) -> &'static str {
+        "kgm"
+    }
+
+    fn kind(&self) -> DecryptorKind {
+        DecryptorKind::Kgm
+    }
+
+    fn probe_magic(&self, magic: &[u8]) -> bool {
+        KGMFormat::from_magic(magic).is_some()
+    }
+
+    fn probe_extension(&self, extension: &str) -> bool {
+        ["kgm", "kgma", "vpr"]
+            .iter()
+            .any(|ext| extension.eq_ignore_ascii_case(ext))
+    }
+
+    fn open<'a>(
+        &self,
+        reader: Box<dyn Read + 'a>,
+        _extension: &str,
+    ) -> Result<Box<dyn EncryptedTrack + 'a>, ParseError> {
+        match &self.mask {
+            Some(mask) => Ok(Box::new(KGMReader::with_mask(reader, mask)?)),
+            None => Ok(Box::new(KGMReader::new(reader)?)),
+        }
+    }
+}
+
+/// `.kwm` files, opened as [`KWMReader`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KWMDecryptor;
+
+impl Decryptor for KWMDecryptor {
+    fn name(&self) -> &'static str {
+        "kwm"
+    }
+
+    fn kind(&self) -> DecryptorKind {
+        DecryptorKind::Kwm
+    }
+
+    fn probe_magic(&self, magic: &[u8]) -> bool {
+        magic.starts_with(&KWM_MAGIC) || magic.starts_with(&KWM_MAGIC_OLD)
+    }
+
+    fn probe_extension(&self, extension: &str) -> bool {
+        extension.eq_ignore_ascii_case("kwm")
+    }
+
+    fn open<'a>(
+        &self,
+        reader: Box<dyn Read + 'a>,
+        _extension: &str,
+    ) -> Result<Box<dyn EncryptedTrack + 'a>, ParseError> {
+        Ok(Box::new(KWMReader::new(reader)?))
+    }
+}
+
+/// QMC files encrypted with the static cipher, opened as [`QMCReader`].
+/// They have no magic header, so the variant comes from the extension.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QMCDecryptor;
+
+impl Decryptor for QMCDecryptor {
+    fn name(&self) -> &'static str {
+        "qmc"
+    }
+
+    fn kind(&self) -> DecryptorKind {
+        DecryptorKind::Qmc
+    }
+
+    fn probe_extension(&self, extension: &str) -> bool {
+        QMCFormat::from_extension(extension).is_some()
+    }
+
+    fn open<'a>(
+        &self,
+        reader: Box<dyn Read + 'a>,
+        extension: &str,
+    ) -> Result<Box<dyn EncryptedTrack + 'a>, ParseError> {
+        let format =
+            QMCFormat::from_extension(extension).ok_or(ParseError::InvalidHeader)?;
+        Ok(Box::new(QMCReader::new(reader, format)))
+    }
+}
+
+/// Netease client cache files, opened as [`CacheReader`]. Their info is
+/// kept in other files, so no metadata is provided.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheDecryptor;
+
+impl Decryptor for CacheDecryptor {
+    fn name(&self) -> &'static str {
+        "cache"
+    }
+
+    fn kind(&self) -> DecryptorKind {
+        DecryptorKind::Cache
+    }
+
+    fn probe_extension(&self, extension: &str) -> bool {
+        extension.eq_ignore_ascii_case("uc") || extension.eq_ignore_ascii_case("uc!")
+    }
+
+    fn open<'a>(
+        &self,
+        reader: Box<dyn Read + 'a>,
+        _extension: &str,
+    ) -> Result<Box<dyn EncryptedTrack + 'a>, ParseError> {
+        Ok(Box::new(CacheReader::new(reader)))
+    }
+}
+
+impl EncryptedTrack for NCMFile {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        Ok(Box::new(self.get_music()?.as_slice()))
+    }
+
+    fn metadata(&mut self) -> Result<Option<Vec<u8>>, ParseError> {
+        match self.get_metadata_kind()? {
+            MetadataKind::None => Ok(None),
+            _ => Ok(Some(self.get_metadata()?.clone())),
+        }
+    }
+
+    fn metadata_kind(&mut self) -> Result<MetadataKind, ParseError> {
+        self.get_metadata_kind()
+    }
+
+    fn raw_metadata(&self) -> Option<&[u8]> {
+        (!self.metadata.is_empty()).then_some(&self.metadata)
+    }
+
+    fn cover(&self) -> Option<&[u8]> {
+        Some(self.get_image_unchecked())
+    }
+}
+
+impl<R: Read> EncryptedTrack for NCMReader<R> {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        Ok(Box::new(self))
+    }
+
+    fn metadata(&mut self) -> Result<Option<Vec<u8>>, ParseError> {
+        match self.decrypted_metadata()? {
+            (MetadataKind::None, _) => Ok(None),
+            (_, metadata) => Ok(Some(metadata.to_vec())),
+        }
+    }
+
+    fn metadata_kind(&mut self) -> Result<MetadataKind, ParseError> {
+        self.decrypted_metadata().map(|(kind, _)| kind)
+    }
+
+    fn raw_metadata(&self) -> Option<&[u8]> {
+        let metadata = self.get_metadata_unchecked();
+        (!metadata.is_empty()).then_some(metadata)
+    }
+
+    fn cover(&self) -> Option<&[u8]> {
+        Some(self.get_image())
+    }
+}
+
+impl EncryptedTrack for KGMFile {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        let Ok(music) = self.get_music();
+        Ok(Box::new(music.as_slice()))
+    }
+}
+
+impl<R: Read> EncryptedTrack for KGMReader<R> {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        Ok(Box::new(self))
+    }
+}
+
+impl EncryptedTrack for KWMFile {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        let Ok(music) = self.get_music();
+        Ok(Box::new(music.as_slice()))
+    }
+}
+
+impl<R: Read> EncryptedTrack for KWMReader<R> {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        Ok(Box::new(self))
+    }
+}
+
+impl EncryptedTrack for QMCFile {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        let Ok(music) = self.get_music();
+        Ok(Box::new(music.as_slice()))
+    }
+}
+
+impl<R: Read> EncryptedTrack for QMCReader<R> {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        Ok(Box::new(self))
+    }
+}
+
+impl<R: Read> EncryptedTrack for CacheReader<R> {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        Ok(Box::new(self))
+    }
+}
@@ -0,0 +1,148 @@
+/// The format of the decrypted music, detected from its first bytes rather
+/// than trusting the metadata.
+/// # Example
+/// ```
+/// use ncm_parser::AudioFormat;
+///
+/// assert_eq!(AudioFormat::from_header(b"fLaC\0\0\0\x22"), Some(AudioFormat::Flac));
+/// assert_eq!(AudioFormat::Flac.extension(), "flac");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    /// MP3, starts with an MPEG audio frame.
+    Mp3,
+    /// FLAC, starts with `fLaC`.
+    Flac,
+    /// Ogg, starts with `OggS`.
+    Ogg,
+    /// WAV, starts with `RIFF`.
+    Wav,
+    /// MPEG-4 audio, with `ftyp` at offset 4.
+    M4a,
+}
+
+impl AudioFormat {
+    /// The number of bytes enough for [`from_header()`](AudioFormat::from_header())
+    /// if the music doesn't start with an ID3v2 tag.
+    pub const SNIFF_LEN: usize = 10;
+
+    /// The number of bytes needed by [`from_header()`](AudioFormat::from_header()),
+    /// given the first [`SNIFF_LEN`](AudioFormat::SNIFF_LEN) bytes of the
+    /// music. It's larger than `SNIFF_LEN` if the music starts with an ID3v2
+    /// tag, which has to be skipped.
+    pub fn sniff_len(header: &[u8]) -> usize {
+        Self::id3_len(header).map_or(Self::SNIFF_LEN, |len| len + Self::SNIFF_LEN)
+    }
+
+    /// Detect the format from the first [`sniff_len()`](AudioFormat::sniff_len())
+    /// bytes of the music. Returns `None` if the format is unknown or
+    /// `header` is too short to tell.
+    pub fn from_header(header: &[u8]) -> Option<Self> {
+        // The ID3v2 tag might be in front of other formats as well, such as
+        // FLAC, so the bytes after it are checked.
+        if let Some(len) = Self::id3_len(header) {
+            return Self::from_header(header.get(len..)?);
+        }
+        match header {
+            // The frame sync, and the layer bits must not be `00`, which
+            // is used by AAC in ADTS.
+            [0xff, x, ..] if x & 0xe0 == 0xe0 && x & 0x06 != 0 => Some(Self::Mp3),
+            [b'f', b'L', b'a', b'C', ..] => Some(Self::Flac),
+            [b'O', b'g', b'g', b'S', ..] => Some(Self::Ogg),
+            [b'R', b'I', b'F', b'F', ..] => Some(Self::Wav),
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some(Self::M4a),
+            _ => None,
+        }
+    }
+
+    /// The length of the ID3v2 tag at the beginning of `header`, including the
+    /// 10-byte header and the footer if any. Returns `None` if `header` doesn't
+    /// start with a complete ID3v2 header.
+    pub fn id3_len(header: &[u8]) -> Option<usize> {
+        let [b'I', b'D', b'3', _, _, flags, size @ ..] =
+            *header.first_chunk::<10>()?
+        else {
+            return None;
+        };
+        // The size excluding the header is a syncsafe integer.
+        if size.iter().any(|x| x & 0x80 != 0) {
+            return None;
+        }
+        let size = size.iter().fold(0, |size, x| size << 7 | *x as usize);
+        let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+        Some(10 + size + footer)
+    }
+
+    /// The file extension without the leading dot, such as `mp3`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Mp3 => "mp3",
+            Self::Flac => "flac",
+            Self::Ogg => "ogg",
+            Self::Wav => "wav",
+            Self::M4a => "m4a",
+        }
+    }
+}
+
+/// The format of the cover image, detected from its first bytes rather than
+/// the extension of `album_pic_url`.
+/// # Example
+/// ```
+/// use ncm_parser::ImageFormat;
+///
+/// let format = ImageFormat::from_header(b"\x89PNG\r\n\x1a\n").unwrap();
+/// assert_eq!(format, ImageFormat::Png);
+/// assert_eq!(format.mime_type(), "image/png");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// JPEG, starts with `FF D8 FF`.
+    Jpeg,
+    /// PNG, starts with `89 50 4E 47 0D 0A 1A 0A`.
+    Png,
+    /// WebP, starts with `RIFF` and has `WEBP` at offset 8.
+    WebP,
+    /// GIF, starts with `GIF87a` or `GIF89a`.
+    Gif,
+}
+
+impl ImageFormat {
+    /// The number of bytes enough for [`from_header()`](ImageFormat::from_header()).
+    pub const SNIFF_LEN: usize = 12;
+
+    /// Detect the format from the first [`SNIFF_LEN`](ImageFormat::SNIFF_LEN)
+    /// bytes of the image. Returns `None` if the format is unknown or
+    /// `header` is too short to tell.
+    pub fn from_header(header: &[u8]) -> Option<Self> {
+        match header {
+            [0xff, 0xd8, 0xff, ..] => Some(Self::Jpeg),
+            [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, ..] => Some(Self::Png),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => {
+                Some(Self::WebP)
+            }
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Self::Gif),
+            _ => None,
+        }
+    }
+
+    /// The file extension without the leading dot, such as `jpg`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::WebP => "webp",
+            Self::Gif => "gif",
+        }
+    }
+
+    /// The MIME type, such as `image/jpeg`.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::WebP => "image/webp",
+            Self::Gif => "image/gif",
+        }
+    }
+}
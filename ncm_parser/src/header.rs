@@ -0,0 +1,288 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::ParseError::{self, *};
+use crate::{
+    decrypt_metadata, read_exact_reader, ImageFormat, MetadataKind, NCMKey, Segment,
+};
+#[cfg(feature = "serde")]
+use crate::{parse_json, parse_song, DJMetadata, NCMMetadata};
+
+/// Parse everything in front of the music data, the music data itself is
+/// never read. Recommended if you only need the metadata or cover image.
+/// The reader is left at the beginning of the music data.
+/// # Example
+/// ```no_run
+/// let header = ncm_parser::parse_header(std::fs::File::open("xxx.ncm").unwrap()).unwrap();
+/// let metadata = header.get_metadata().unwrap();
+/// ```
+pub fn parse_header<R: Read>(mut reader: R) -> Result<NCMHeader, ParseError> {
+    let mut walk = HeaderWalk::new(false);
+    while let Some((segment, len)) = walk.next_read() {
+        let data = read_exact_reader(&mut reader, len, segment, &mut walk.offset)?;
+        walk.feed(data)?;
+    }
+    Ok(walk.header)
+}
+
+/// Same as [`parse_header()`], but the image segment is skipped via [`Seek`]
+/// as well. [`NCMHeader::get_image()`] always returns empty data then.
+/// The reader is left at the beginning of the music data.
+/// # Example
+/// ```no_run
+/// let header =
+///     ncm_parser::parse_header_skip_image(std::fs::File::open("xxx.ncm").unwrap())
+///         .unwrap();
+/// let metadata = header.get_metadata().unwrap();
+/// ```
+pub fn parse_header_skip_image<R: Read + Seek>(
+    mut reader: R,
+) -> Result<NCMHeader, ParseError> {
+    let mut walk = HeaderWalk::new(true);
+    while let Some((segment, len)) = walk.next_read() {
+        let data = read_exact_reader(&mut reader, len, segment, &mut walk.offset)?;
+        walk.feed(data)?;
+    }
+    let header = walk.header;
+    let image_frame_len = header.image_len.max(header.image_frame_len);
+    if let Err(source) = reader.seek(SeekFrom::Current(image_frame_len as i64)) {
+        return Err(Io {
+            segment: Segment::Image,
+            offset: walk.offset,
+            source,
+        });
+    }
+    Ok(header)
+}
+
+/// The parts in front of the music data, which are read one by one.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Magic,
+    KeyLen,
+    Key(u32),
+    MetadataLen,
+    Metadata(u32),
+    Gap,
+    ImageLen,
+    Image(u32),
+    Padding(u32),
+    Done,
+}
+
+/// Walk through everything in front of the music data. It only tells how
+/// many bytes to read next and checks the bytes read, so the layout is
+/// shared by the sync and async parsers, which do the reading.
+#[derive(Debug, Clone)]
+pub(crate) struct HeaderWalk {
+    /// The position of the reader in the ncm file.
+    pub(crate) offset: u64,
+    /// The header parsed so far.
+    pub(crate) header: NCMHeader,
+    step: Step,
+    skip_image: bool,
+}
+
+impl HeaderWalk {
+    /// Stop in front of the image data if `skip_image` is set, after
+    /// [`NCMHeader::image_len()`] is read.
+    pub(crate) fn new(skip_image: bool) -> Self {
+        Self {
+            offset: 0,
+            header: NCMHeader {
+                rc4_key: Vec::new(),
+                metadata: Vec::new(),
+                crc32: 0,
+                image_frame_len: 0,
+                image: Vec::new(),
+                image_len: 0,
+            },
+            step: Step::Magic,
+            skip_image,
+        }
+    }
+
+    /// The segment and the number of bytes to read next, or `None` if
+    /// everything is read.
+    pub(crate) fn next_read(&self) -> Option<(Segment, u64)> {
+        let (segment, len) = match self.step {
+            Step::Magic => (Segment::Header, 10),
+            Step::KeyLen => (Segment::Key, 4),
+            Step::Key(len) => (Segment::Key, len),
+            Step::MetadataLen => (Segment::Metadata, 4),
+            Step::Metadata(len) => (Segment::Metadata, len),
+            Step::Gap => (Segment::Gap, 9),
+            Step::ImageLen => (Segment::Image, 4),
+            Step::Image(len) | Step::Padding(len) => (Segment::Image, len),
+            Step::Done => return None,
+        };
+        Some((segment, len as u64))
+    }
+
+    /// Take the bytes requested by [`next_read()`](HeaderWalk::next_read()).
+    pub(crate) fn feed(&mut self, mut data: Vec<u8>) -> Result<(), ParseError> {
+        let header = &mut self.header;
+        self.step = match self.step {
+            Step::Magic if data[0..8] != *b"CTENFDAM" => return Err(InvalidHeader),
+            Step::Magic => Step::KeyLen,
+            Step::KeyLen => Step::Key(le_u32(&data)),
+            Step::Key(_) => {
+                data.iter_mut().for_each(|x| *x ^= 0x64);
+                header.rc4_key = data;
+                Step::MetadataLen
+            }
+            Step::MetadataLen => Step::Metadata(le_u32(&data)),
+            Step::Metadata(_) => {
+                data.iter_mut().for_each(|x| *x ^= 0x63);
+                header.metadata = data;
+                Step::Gap
+            }
+            Step::Gap => {
+                header.crc32 = le_u32(&data[0..4]);
+                header.image_frame_len = le_u32(&data[5..9]);
+                Step::ImageLen
+            }
+            Step::ImageLen => {
+                header.image_len = le_u32(&data);
+                if self.skip_image {
+                    Step::Done
+                } else {
+                    Step::Image(header.image_len)
+                }
+            }
+            Step::Image(len) => {
+                header.image = data;
+                Step::Padding(header.image_frame_len.saturating_sub(len))
+            }
+            Step::Padding(_) | Step::Done => Step::Done,
+        };
+        Ok(())
+    }
+}
+
+fn le_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data.try_into().unwrap())
+}
+
+/// A struct contains everything in front of the music data.
+/// ```text
+/// +--------+-----+-----------------+------------------+-------+-----+-----------------+
+/// | header | gap | RC4 key segment | metadata segment | CRC32 | gap |   image frame   |
+/// |   8    |  2  |   4 + key_len   | 4 + metadata_len |   4   |  1  | 4 + 4 + frame   |
+/// +--------+-----+-----------------+------------------+-------+-----+-----------------+
+///
+/// +---------------------------------------------------------------+
+/// |                          image frame                          |
+/// +-----------------+-----------------+------------+--------------+
+/// | image_frame_len |    image_len    | image data |   padding    |
+/// |        4        |        4        | image_len  | frame - len  |
+/// +-----------------+-----------------+------------+--------------+
+/// ```
+/// Old files store the same or a smaller value in `image_frame_len`,
+/// in which case there is no padding.
+#[derive(Debug, Clone)]
+pub struct NCMHeader {
+    pub(crate) rc4_key: Vec<u8>,
+    pub(crate) metadata: Vec<u8>,
+    pub(crate) crc32: u32,
+    pub(crate) image_frame_len: u32,
+    pub(crate) image: Vec<u8>,
+    pub(crate) image_len: u32,
+}
+
+impl NCMHeader {
+    /// Get cover image. Usually in PNG or JPEG format.
+    /// Always empty if the header is parsed by [`parse_header_skip_image()`].
+    pub fn get_image(&self) -> &Vec<u8> {
+        &self.image
+    }
+
+    /// Detect the format of the cover image from its first bytes. Returns
+    /// `None` if the file has no cover image or the format is unknown.
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        ImageFormat::from_header(&self.image)
+    }
+
+    /// Get decrypted metadata.
+    pub fn get_metadata(&self) -> Result<Vec<u8>, ParseError> {
+        decrypt_metadata(&self.metadata).map(|(_, metadata)| metadata)
+    }
+
+    /// Get the kind of the metadata.
+    pub fn get_metadata_kind(&self) -> Result<MetadataKind, ParseError> {
+        decrypt_metadata(&self.metadata).map(|(kind, _)| kind)
+    }
+
+    /// Directly get metadata, which is not decrypted.
+    pub fn get_metadata_unchecked(&self) -> &Vec<u8> {
+        &self.metadata
+    }
+
+    #[cfg(feature = "serde")]
+    /// Parse the JSON format metadata into struct.
+    /// For radio or podcast programs, the song in `mainMusic` is returned.
+    pub fn get_parsed_metadata(&self) -> Result<NCMMetadata, ParseError> {
+        let (kind, metadata) = decrypt_metadata(&self.metadata)?;
+        parse_song(kind, &metadata)
+    }
+
+    #[cfg(feature = "serde")]
+    /// Parse the JSON format metadata of radio or podcast programs into struct.
+    pub fn get_parsed_dj_metadata(&self) -> Result<DJMetadata, ParseError> {
+        parse_json(&self.get_metadata()?)
+    }
+
+    /// Decrypt the RC4 key, which can be used to decrypt the music with
+    /// [`decrypt_music_in_place()`](crate::decrypt_music_in_place()).
+    pub fn get_key(&self) -> Result<NCMKey, ParseError> {
+        NCMKey::new(&self.rc4_key)
+    }
+
+    /// The offset of the encrypted RC4 key in the ncm file.
+    pub fn key_offset(&self) -> u64 {
+        10 + 4
+    }
+
+    /// The length of the encrypted RC4 key.
+    pub fn key_len(&self) -> u32 {
+        self.rc4_key.len() as u32
+    }
+
+    /// The offset of the encrypted metadata in the ncm file.
+    pub fn metadata_offset(&self) -> u64 {
+        self.key_offset() + self.key_len() as u64 + 4
+    }
+
+    /// The length of the encrypted metadata.
+    pub fn metadata_len(&self) -> u32 {
+        self.metadata.len() as u32
+    }
+
+    /// The CRC32 stored in front of the image segment. It is not verified,
+    /// since what the clients calculate it from is unknown.
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    /// The space reserved for the cover image, which may be larger than
+    /// [`image_len()`](NCMHeader::image_len()). The music data starts after
+    /// the reserved space. Old files may store a smaller value, which is
+    /// ignored then.
+    pub fn image_frame_len(&self) -> u32 {
+        self.image_frame_len
+    }
+
+    /// The offset of the cover image in the ncm file.
+    pub fn image_offset(&self) -> u64 {
+        self.metadata_offset() + self.metadata_len() as u64 + 9 + 4
+    }
+
+    /// The length of the cover image, even if the image is skipped.
+    pub fn image_len(&self) -> u32 {
+        self.image_len
+    }
+
+    /// The offset of the music data in the ncm file.
+    pub fn music_offset(&self) -> u64 {
+        self.image_offset() + self.image_len.max(self.image_frame_len) as u64
+    }
+}
@@ -0,0 +1,505 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom};
+
+use md5::{Digest, Md5};
+
+#[cfg(feature = "std")]
+use crate::read_exact_reader;
+use crate::ParseError::{self, *};
+use crate::Segment;
+
+/// The magic header of `.kgm` and `.kgma` files.
+pub const KGM_MAGIC: [u8; 16] = [
+    0x7c, 0xd5, 0x32, 0xeb, 0x86, 0x02, 0x7f, 0x4b, 0xa8, 0xaf, 0xa6, 0x8e, 0x0f,
+    0xff, 0x99, 0x14,
+];
+/// The magic header of `.vpr` files.
+pub const VPR_MAGIC: [u8; 16] = [
+    0x05, 0x28, 0xbc, 0x96, 0xe9, 0xe4, 0x5a, 0x43, 0x91, 0xaa, 0xbd, 0xd0, 0x7a,
+    0xf5, 0x36, 0x31,
+];
+
+/// `.vpr` files are XORed with it once more after the rest of the cipher.
+const VPR_MASK_DIFF: [u8; 17] = [
+    0x25, 0xdf, 0xe8, 0xa6, 0x75, 0x1e, 0x75, 0x0e, 0x2f, 0x80, 0xf3, 0x2d, 0xb8,
+    0xb6, 0xe3, 0x11, 0x00,
+];
+
+/// The length of the fixed part of the header, see [`KGMHeader`].
+const HEADER_LEN: usize = 0x3c;
+
+/// The containers of Kugou Music.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KGMFormat {
+    /// `.kgm` or `.kgma`, starts with [`KGM_MAGIC`].
+    Kgm,
+    /// `.vpr`, starts with [`VPR_MAGIC`].
+    Vpr,
+}
+
+impl KGMFormat {
+    /// Get the container from the first 16 bytes of the file.
+    pub fn from_magic(magic: &[u8]) -> Option<Self> {
+        if magic.starts_with(&KGM_MAGIC) {
+            Some(Self::Kgm)
+        } else if magic.starts_with(&VPR_MAGIC) {
+            Some(Self::Vpr)
+        } else {
+            None
+        }
+    }
+}
+
+/// The MD5 used by Kugou, whose 2-byte pairs are in reverse order.
+fn kugou_md5(data: &[u8]) -> [u8; 16] {
+    let digest = Md5::digest(data);
+    core::array::from_fn(|i| digest[14 - i / 2 * 2 + i % 2])
+}
+
+/// The mask table of the older Kugou Music files, which are not encrypted
+/// with crypto version 3. Byte `i` of the table masks byte `i` of the audio
+/// data. The table is not shipped with this crate, load it from the mask
+/// file used by other decoders instead.
+///
+/// Experimental: the mask path follows the reference decoders, but it has
+/// never been checked against a real mask table or file.
+/// # Example
+/// ```no_run
+/// let mask = ncm_parser::KGMMask::new(std::fs::read("kgm.mask").unwrap());
+/// let data = std::fs::read("xxx.kgm").unwrap();
+/// let mut kgm_file = ncm_parser::KGMFile::with_mask(data, &mask).unwrap();
+/// let music = kgm_file.get_music().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct KGMMask(Arc<[u8]>);
+
+impl KGMMask {
+    /// Wrap the mask table. Cloning it afterwards is cheap.
+    pub fn new(table: Vec<u8>) -> Self {
+        Self(table.into())
+    }
+}
+
+impl core::fmt::Debug for KGMMask {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("KGMMask")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+/// The cipher XORed with the per-file key.
+#[derive(Debug, Clone)]
+enum KGMCipher {
+    /// Crypto version 3, with the box built from the slot key.
+    V3([u8; 16]),
+    /// The older files XORed with the mask table.
+    Mask(KGMMask),
+}
+
+/// The key built from the per-file key in the header and the crypto slot or
+/// the mask table.
+#[derive(Debug, Clone)]
+struct KGMKey {
+    cipher: KGMCipher,
+    file_box: [u8; 17],
+    is_vpr: bool,
+}
+
+impl KGMKey {
+    /// Crypto version 3 is supported if its slot key is known, the other
+    /// versions are supported only with the mask table.
+    fn new(
+        header: &KGMHeader,
+        data: &[u8; HEADER_LEN],
+        mask: Option<&KGMMask>,
+    ) -> Result<Self, ParseError> {
+        let (cipher, file_box) =
+            match (header.crypto_version, header.crypto_slot, mask) {
+                (3, 1, _) => {
+                    let slot_box = kugou_md5(&[0x6c, 0x2c, 0x2f, 0x27]);
+                    let file_md5 = kugou_md5(&data[0x2c..0x3c]);
+                    let file_box = core::array::from_fn(|i| {
+                        file_md5.get(i).copied().unwrap_or(0x6b)
+                    });
+                    (KGMCipher::V3(slot_box), file_box)
+                }
+                (version, _, Some(mask)) if version != 3 => {
+                    let file_box = core::array::from_fn(|i| {
+                        data[0x1c..0x2c].get(i).copied().unwrap_or(0)
+                    });
+                    (KGMCipher::Mask(mask.clone()), file_box)
+                }
+                (version, slot, _) => {
+                    return Err(UnsupportedCrypto { version, slot })
+                }
+            };
+        Ok(Self {
+            cipher,
+            file_box,
+            is_vpr: header.format == KGMFormat::Vpr,
+        })
+    }
+
+    /// Returns [`MaskTooShort`] if the mask table ends before `len` bytes
+    /// of the audio data.
+    fn check_len(&self, len: u64) -> Result<(), ParseError> {
+        match &self.cipher {
+            KGMCipher::Mask(mask) if (mask.0.len() as u64) < len => {
+                Err(MaskTooShort {
+                    len: mask.0.len() as u64,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Decrypt `buf` in place, where `offset` is the position of `buf[0]` in
+    /// the audio data. The mask table, if any, should be checked with
+    /// [`check_len()`](KGMKey::check_len()) beforehand.
+    fn apply(&self, buf: &mut [u8], offset: u64) {
+        for (x, offset) in buf.iter_mut().zip(offset..) {
+            *x ^= self.file_box[(offset % 17) as usize];
+            *x ^= *x << 4;
+            match &self.cipher {
+                KGMCipher::V3(slot_box) => {
+                    *x ^= slot_box[(offset % 16) as usize];
+                    let [a, b, c, d] = (offset as u32).to_le_bytes();
+                    *x ^= a ^ b ^ c ^ d;
+                }
+                KGMCipher::Mask(mask) => {
+                    let mask = mask.0[offset as usize];
+                    *x ^= mask ^ (mask << 4);
+                }
+            }
+            if self.is_vpr {
+                *x ^= VPR_MASK_DIFF[(offset % 17) as usize];
+            }
+        }
+    }
+}
+
+/// The header of a Kugou Music file.
+/// ```text
+/// +-------+--------------+----------------+-------------+-----------+----------+---------+
+/// | magic | audio_offset | crypto_version | crypto_slot | test data | file key | unknown |
+/// |  16   |      4       |       4        |      4      |    16     |    16    |   ...   |
+/// +-------+--------------+----------------+-------------+-----------+----------+---------+
+/// ```
+/// The audio data starts at `audio_offset`. The older files encrypted with
+/// the mask table use the 16 bytes of test data as the per-file key.
+#[derive(Debug, Clone)]
+pub struct KGMHeader {
+    format: KGMFormat,
+    audio_offset: u32,
+    crypto_version: u32,
+    crypto_slot: u32,
+}
+
+impl KGMHeader {
+    /// Parse the fixed part of the header, returns it along with the key.
+    fn new(
+        data: &[u8; HEADER_LEN],
+        mask: Option<&KGMMask>,
+    ) -> Result<(Self, KGMKey), ParseError> {
+        let format = KGMFormat::from_magic(data).ok_or(InvalidHeader)?;
+        let u32_at =
+            |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+        let header = Self {
+            format,
+            audio_offset: u32_at(0x10),
+            crypto_version: u32_at(0x14),
+            crypto_slot: u32_at(0x18),
+        };
+        if (header.audio_offset as usize) < HEADER_LEN {
+            return Err(InvalidHeader);
+        }
+        let key = KGMKey::new(&header, data, mask)?;
+        Ok((header, key))
+    }
+
+    /// The container of the file.
+    pub fn format(&self) -> KGMFormat {
+        self.format
+    }
+
+    /// The offset of the audio data in the file.
+    pub fn audio_offset(&self) -> u32 {
+        self.audio_offset
+    }
+
+    /// The version of the cipher. Version 3 is supported with its known
+    /// slot keys, the others only with the [`KGMMask`].
+    pub fn crypto_version(&self) -> u32 {
+        self.crypto_version
+    }
+
+    /// The slot of the key used along with the per-file key.
+    pub fn crypto_slot(&self) -> u32 {
+        self.crypto_slot
+    }
+}
+
+#[cfg(feature = "std")]
+/// Read the header and skip to the beginning of the audio data.
+fn parse_header<R: Read>(
+    reader: &mut R,
+    mask: Option<&KGMMask>,
+) -> Result<(KGMHeader, KGMKey), ParseError> {
+    let mut offset = 0;
+    let data =
+        read_exact_reader(reader, HEADER_LEN as u64, Segment::Header, &mut offset)?;
+    let (header, key) = KGMHeader::new(data.as_slice().try_into().unwrap(), mask)?;
+    let rest = header.audio_offset as u64 - offset;
+    read_exact_reader(reader, rest, Segment::Header, &mut offset)?;
+    Ok((header, key))
+}
+
+/// A struct contains all the data parsed from the Kugou Music file.
+///
+/// `.kgm` and `.vpr` files of crypto version 3 with key slot 1 are
+/// supported. The older files XORed with the mask table can be tried with
+/// the experimental [`KGMMask`], see [`with_mask()`](KGMFile::with_mask()).
+/// # Example
+/// ```no_run
+/// let data = std::fs::read("xxx.kgm").unwrap();
+/// let mut kgm_file = ncm_parser::KGMFile::new(data).unwrap();
+/// let music = kgm_file.get_music().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct KGMFile {
+    is_decrypted: bool,
+    header: KGMHeader,
+    key: KGMKey,
+    music: Vec<u8>,
+}
+
+impl KGMFile {
+    /// Parse the content of a Kugou Music file. Returns [`InvalidHeader`]
+    /// if it is not a `.kgm` or `.vpr` file, or [`UnsupportedCrypto`] if
+    /// the cipher is unknown.
+    pub fn new(data: Vec<u8>) -> Result<Self, ParseError> {
+        Self::new_inner(data, None)
+    }
+
+    /// Same as [`new()`](KGMFile::new()), but the older files are decrypted
+    /// with `mask` as well. Returns [`MaskTooShort`] if the mask table is
+    /// shorter than their audio data.
+    pub fn with_mask(data: Vec<u8>, mask: &KGMMask) -> Result<Self, ParseError> {
+        Self::new_inner(data, Some(mask))
+    }
+
+    fn new_inner(
+        mut data: Vec<u8>,
+        mask: Option<&KGMMask>,
+    ) -> Result<Self, ParseError> {
+        let Some(fixed) = data.first_chunk::<HEADER_LEN>() else {
+            return Err(EndOfFile {
+                segment: Segment::Header,
+                offset: 0,
+                expected: HEADER_LEN as u64,
+                available: data.len() as u64,
+            });
+        };
+        let (header, key) = KGMHeader::new(fixed, mask)?;
+        let audio_offset = header.audio_offset as usize;
+        if data.len() < audio_offset {
+            return Err(EndOfFile {
+                segment: Segment::Header,
+                offset: HEADER_LEN as u64,
+                expected: (audio_offset - HEADER_LEN) as u64,
+                available: (data.len() - HEADER_LEN) as u64,
+            });
+        }
+        data.drain(..audio_offset);
+        key.check_len(data.len() as u64)?;
+        Ok(Self {
+            is_decrypted: false,
+            header,
+            key,
+            music: data,
+        })
+    }
+
+    #[cfg(feature = "std")]
+    /// Parse the Kugou Music file with reader.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, ParseError> {
+        Self::from_reader_inner(reader, None)
+    }
+
+    #[cfg(feature = "std")]
+    /// Same as [`from_reader()`](KGMFile::from_reader()), but the older
+    /// files are decrypted with `mask` as well.
+    pub fn from_reader_with_mask<R: Read>(
+        reader: R,
+        mask: &KGMMask,
+    ) -> Result<Self, ParseError> {
+        Self::from_reader_inner(reader, Some(mask))
+    }
+
+    #[cfg(feature = "std")]
+    fn from_reader_inner<R: Read>(
+        mut reader: R,
+        mask: Option<&KGMMask>,
+    ) -> Result<Self, ParseError> {
+        let (header, key) = parse_header(&mut reader, mask)?;
+        let mut music = Vec::new();
+        if let Err(source) = reader.read_to_end(&mut music) {
+            return Err(Io {
+                segment: Segment::Music,
+                offset: header.audio_offset as u64 + music.len() as u64,
+                source,
+            });
+        }
+        key.check_len(music.len() as u64)?;
+        Ok(Self {
+            is_decrypted: false,
+            header,
+            key,
+            music,
+        })
+    }
+
+    /// Get music. Usually in MP3 or FLAC format.
+    /// This function contains the decrypting precedure if calling the first time,
+    /// and directly return the decrypted data after first-time calling.
+    pub fn get_music(&mut self) -> Result<&Vec<u8>, !> {
+        if !self.is_decrypted {
+            self.is_decrypted = true;
+            self.key.apply(&mut self.music, 0);
+        }
+        Ok(&self.music)
+    }
+
+    /// Directly get music.
+    /// The music data is not decrypted if [`get_music()`](KGMFile::get_music()) has never been called.
+    pub fn get_music_unchecked(&self) -> &Vec<u8> {
+        &self.music
+    }
+
+    /// Decrypt the music into `dst` without touching the music kept in
+    /// [`KGMFile`], returns the number of bytes decrypted, which is the
+    /// smaller one of the music length and `dst.len()`.
+    pub fn decrypt_music_into(&self, dst: &mut [u8]) -> usize {
+        let len = dst.len().min(self.music.len());
+        dst[..len].copy_from_slice(&self.music[..len]);
+        if !self.is_decrypted {
+            self.key.apply(&mut dst[..len], 0);
+        }
+        len
+    }
+
+    /// Get the header of the file.
+    pub fn get_header(&self) -> &KGMHeader {
+        &self.header
+    }
+}
+
+/// A streaming Kugou Music parser. The header is parsed when constructing,
+/// and the audio data is decrypted on the fly through [`Read`]. Seeking is
+/// supported if the underlying reader implements [`Seek`].
+/// # Example
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut reader = ncm_parser::KGMReader::new(File::open("xxx.kgm").unwrap()).unwrap();
+/// let mut music = File::create("xxx.flac").unwrap();
+/// std::io::copy(&mut reader, &mut music).unwrap();
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct KGMReader<R> {
+    inner: R,
+    header: KGMHeader,
+    key: KGMKey,
+    pos: u64,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> KGMReader<R> {
+    /// Parse the header. The reader is left at the beginning of the audio data.
+    pub fn new(reader: R) -> Result<Self, ParseError> {
+        Self::new_inner(reader, None)
+    }
+
+    /// Same as [`new()`](KGMReader::new()), but the older files are
+    /// decrypted with `mask` as well. Reading beyond the mask table fails
+    /// with [`MaskTooShort`].
+    pub fn with_mask(reader: R, mask: &KGMMask) -> Result<Self, ParseError> {
+        Self::new_inner(reader, Some(mask))
+    }
+
+    fn new_inner(mut reader: R, mask: Option<&KGMMask>) -> Result<Self, ParseError> {
+        let (header, key) = parse_header(&mut reader, mask)?;
+        Ok(Self {
+            inner: reader,
+            header,
+            key,
+            pos: 0,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R> KGMReader<R> {
+    /// Get the header of the file.
+    pub fn get_header(&self) -> &KGMHeader {
+        &self.header
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// Reading from the underlying reader directly would mess up the
+    /// decryption of the rest music data.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `KGMReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for KGMReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.key
+            .check_len(self.pos + len as u64)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.key.apply(&mut buf[..len], self.pos);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Seek> Seek for KGMReader<R> {
+    /// Same as [`NCMReader`](crate::NCMReader), the position is relative to
+    /// the beginning of the audio data.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let audio_offset = self.header.audio_offset as u64;
+        let pos = match pos {
+            SeekFrom::Start(pos) => SeekFrom::Start(audio_offset + pos),
+            pos => pos,
+        };
+        let pos = self.inner.seek(pos)?;
+        if pos < audio_offset {
+            self.inner.seek(SeekFrom::Start(audio_offset + self.pos))?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a position before the audio data",
+            ));
+        }
+        self.pos = pos - audio_offset;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,307 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom};
+
+#[cfg(feature = "std")]
+use crate::read_exact_reader;
+use crate::ParseError::{self, *};
+use crate::Segment;
+
+/// The magic header of `.kwm` files.
+pub const KWM_MAGIC: [u8; 16] = *b"yeelion-kuwo-tme";
+/// The magic header found in some older `.kwm` files.
+pub const KWM_MAGIC_OLD: [u8; 16] = *b"yeelion-kuwo\0\0\0\0";
+
+/// The key shared by all the `.kwm` files.
+const PREDEFINED_KEY: [u8; 32] = *b"MoOtOiTvINGwd2E6n0E1i7L5t2IoOoNk";
+
+/// The audio data always starts here.
+const AUDIO_OFFSET: usize = 0x400;
+
+/// The key derived from the resource id in the header.
+#[derive(Debug, Clone)]
+struct KWMKey([u8; 32]);
+
+impl KWMKey {
+    /// The decimal resource id is repeated to 32 bytes, then XORed with
+    /// [`PREDEFINED_KEY`].
+    fn new(resource_id: u64) -> Self {
+        let id = resource_id.to_string().into_bytes();
+        Self(core::array::from_fn(|i| {
+            PREDEFINED_KEY[i] ^ id[i % id.len()]
+        }))
+    }
+
+    /// Decrypt `buf` in place, where `offset` is the position of `buf[0]` in
+    /// the audio data.
+    fn apply(&self, buf: &mut [u8], offset: u64) {
+        for (x, offset) in buf.iter_mut().zip(offset..) {
+            *x ^= self.0[(offset % 32) as usize];
+        }
+    }
+}
+
+/// The header of a Kuwo Music file.
+/// ```text
+/// +-------+---------+-------------+---------+----------------+---------+
+/// | magic | unknown | resource_id | unknown | bitrate+format | unknown |
+/// |  16   |    8    |      8      |    16   |       8        |   ...   |
+/// +-------+---------+-------------+---------+----------------+---------+
+/// ```
+/// The audio data starts at `0x400`.
+#[derive(Debug, Clone)]
+pub struct KWMHeader {
+    resource_id: u64,
+    bitrate: u32,
+    format: String,
+}
+
+impl KWMHeader {
+    /// Parse the header, returns it along with the key.
+    fn new(data: &[u8; AUDIO_OFFSET]) -> Result<(Self, KWMKey), ParseError> {
+        if data[..16] != KWM_MAGIC && data[..16] != KWM_MAGIC_OLD {
+            return Err(InvalidHeader);
+        }
+        let resource_id = u64::from_le_bytes(data[0x18..0x20].try_into().unwrap());
+        // Something like `320kmp3` or `2000kflac`, padded with zeros.
+        let tag = &data[0x30..0x38];
+        let tag = &tag[..tag.iter().position(|x| *x == 0).unwrap_or(tag.len())];
+        let split = tag
+            .iter()
+            .position(|x| !x.is_ascii_digit())
+            .unwrap_or(tag.len());
+        let (bitrate, format) = tag.split_at(split);
+        let format = format.strip_prefix(b"k").unwrap_or(format);
+        let header = Self {
+            resource_id,
+            bitrate: core::str::from_utf8(bitrate)
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(0),
+            format: String::from_utf8_lossy(format).to_ascii_lowercase(),
+        };
+        Ok((header, KWMKey::new(resource_id)))
+    }
+
+    /// The resource id of the song, which the key is derived from.
+    pub fn resource_id(&self) -> u64 {
+        self.resource_id
+    }
+
+    /// The bitrate in kbps. Zero if missing or invalid.
+    pub fn bitrate(&self) -> u32 {
+        self.bitrate
+    }
+
+    /// The format of the music, such as `mp3` or `flac`, which might be
+    /// truncated since only 8 bytes are reserved for it along with the
+    /// bitrate. Empty if missing.
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    /// The offset of the audio data in the file, which is always `0x400`.
+    pub fn audio_offset(&self) -> u32 {
+        AUDIO_OFFSET as u32
+    }
+}
+
+#[cfg(feature = "std")]
+/// Read the header, the reader is left at the beginning of the audio data.
+fn parse_header<R: Read>(reader: &mut R) -> Result<(KWMHeader, KWMKey), ParseError> {
+    let mut offset = 0;
+    let data = read_exact_reader(
+        reader,
+        AUDIO_OFFSET as u64,
+        Segment::Header,
+        &mut offset,
+    )?;
+    KWMHeader::new(data.as_slice().try_into().unwrap())
+}
+
+/// A struct contains all the data parsed from the Kuwo Music file.
+/// # Example
+/// ```no_run
+/// let data = std::fs::read("xxx.kwm").unwrap();
+/// let mut kwm_file = ncm_parser::KWMFile::new(data).unwrap();
+/// let music = kwm_file.get_music().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct KWMFile {
+    is_decrypted: bool,
+    header: KWMHeader,
+    key: KWMKey,
+    music: Vec<u8>,
+}
+
+impl KWMFile {
+    /// Parse the content of a Kuwo Music file. Returns [`InvalidHeader`]
+    /// if it is not a `.kwm` file.
+    pub fn new(mut data: Vec<u8>) -> Result<Self, ParseError> {
+        let Some(header) = data.first_chunk::<AUDIO_OFFSET>() else {
+            return Err(EndOfFile {
+                segment: Segment::Header,
+                offset: 0,
+                expected: AUDIO_OFFSET as u64,
+                available: data.len() as u64,
+            });
+        };
+        let (header, key) = KWMHeader::new(header)?;
+        data.drain(..AUDIO_OFFSET);
+        Ok(Self {
+            is_decrypted: false,
+            header,
+            key,
+            music: data,
+        })
+    }
+
+    #[cfg(feature = "std")]
+    /// Parse the Kuwo Music file with reader.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, ParseError> {
+        let (header, key) = parse_header(&mut reader)?;
+        let mut music = Vec::new();
+        if let Err(source) = reader.read_to_end(&mut music) {
+            return Err(Io {
+                segment: Segment::Music,
+                offset: AUDIO_OFFSET as u64 + music.len() as u64,
+                source,
+            });
+        }
+        Ok(Self {
+            is_decrypted: false,
+            header,
+            key,
+            music,
+        })
+    }
+
+    /// Get music. Usually in MP3 or FLAC format, see
+    /// [`KWMHeader::format()`].
+    /// This function contains the decrypting precedure if calling the first time,
+    /// and directly return the decrypted data after first-time calling.
+    pub fn get_music(&mut self) -> Result<&Vec<u8>, !> {
+        if !self.is_decrypted {
+            self.is_decrypted = true;
+            self.key.apply(&mut self.music, 0);
+        }
+        Ok(&self.music)
+    }
+
+    /// Directly get music.
+    /// The music data is not decrypted if [`get_music()`](KWMFile::get_music()) has never been called.
+    pub fn get_music_unchecked(&self) -> &Vec<u8> {
+        &self.music
+    }
+
+    /// Decrypt the music into `dst` without touching the music kept in
+    /// [`KWMFile`], returns the number of bytes decrypted, which is the
+    /// smaller one of the music length and `dst.len()`.
+    pub fn decrypt_music_into(&self, dst: &mut [u8]) -> usize {
+        let len = dst.len().min(self.music.len());
+        dst[..len].copy_from_slice(&self.music[..len]);
+        if !self.is_decrypted {
+            self.key.apply(&mut dst[..len], 0);
+        }
+        len
+    }
+
+    /// Get the header of the file.
+    pub fn get_header(&self) -> &KWMHeader {
+        &self.header
+    }
+}
+
+/// A streaming Kuwo Music parser. The header is parsed when constructing,
+/// and the audio data is decrypted on the fly through [`Read`]. Seeking is
+/// supported if the underlying reader implements [`Seek`].
+/// # Example
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut reader = ncm_parser::KWMReader::new(File::open("xxx.kwm").unwrap()).unwrap();
+/// let mut music = File::create("xxx.mp3").unwrap();
+/// std::io::copy(&mut reader, &mut music).unwrap();
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct KWMReader<R> {
+    inner: R,
+    header: KWMHeader,
+    key: KWMKey,
+    pos: u64,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> KWMReader<R> {
+    /// Parse the header. The reader is left at the beginning of the audio data.
+    pub fn new(mut reader: R) -> Result<Self, ParseError> {
+        let (header, key) = parse_header(&mut reader)?;
+        Ok(Self {
+            inner: reader,
+            header,
+            key,
+            pos: 0,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R> KWMReader<R> {
+    /// Get the header of the file.
+    pub fn get_header(&self) -> &KWMHeader {
+        &self.header
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// Reading from the underlying reader directly would mess up the
+    /// decryption of the rest music data.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `KWMReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for KWMReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.key.apply(&mut buf[..len], self.pos);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Seek> Seek for KWMReader<R> {
+    /// Same as [`NCMReader`](crate::NCMReader), the position is relative to
+    /// the beginning of the audio data.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let audio_offset = AUDIO_OFFSET as u64;
+        let pos = match pos {
+            SeekFrom::Start(pos) => SeekFrom::Start(audio_offset + pos),
+            pos => pos,
+        };
+        let pos = self.inner.seek(pos)?;
+        if pos < audio_offset {
+            self.inner.seek(SeekFrom::Start(audio_offset + self.pos))?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a position before the audio data",
+            ));
+        }
+        self.pos = pos - audio_offset;
+        Ok(self.pos)
+    }
+}
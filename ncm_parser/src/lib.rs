@@ -6,6 +6,10 @@
 //!
 //! 一个单文件实现的 .ncm 解析器。
 //!
+//! The crate is `no_std` (with `alloc`) if the default `std` feature is
+//! disabled. Use [`from_slice()`] or [`from_iter()`] to parse an ncm file
+//! stored in memory then.
+//!
 //! # Examples
 //! ```
 //! use ncm_parser::NCMMetadata;
@@ -51,8 +55,8 @@
 //!     let music_name = ncm_file_name.with_extension(&ncm_meta.format);
 //!     std::fs::write(music_name, &music).unwrap();
 //!
-//!     // Get image format
-//!     let image_ext = ncm_meta.album_pic_url.rsplit_once('.').unwrap().1;
+//!     // Get image format from the image itself
+//!     let image_ext = ncm_file_from_iter.image_format().unwrap().extension();
 //!
 //!     // Save cover image
 //!     let image_name = ncm_file_name.with_extension(image_ext);
@@ -64,13 +68,19 @@
 //! }
 //! ```
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![feature(never_type)]
 #![feature(iter_next_chunk)]
 #![feature(iter_advance_by)]
-#![feature(iterator_try_collect)]
 #![feature(doc_auto_cfg)]
 #![warn(missing_docs)]
-use std::io::Read;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
 
 use aes::Aes128Dec;
 use base64::engine::general_purpose::STANDARD as base64dec;
@@ -79,15 +89,98 @@ use cipher::block_padding::Pkcs7;
 use cipher::{BlockDecrypt, KeyInit};
 use thiserror::Error;
 
+#[cfg(feature = "serde")]
+use metadata::{parse_json, parse_song};
+
 use ParseError::*;
 
+#[cfg(feature = "tokio")]
+mod async_reader;
+mod builder;
+mod cache;
+#[cfg(feature = "std")]
+mod decryptor;
+mod format;
+#[cfg(feature = "std")]
+mod header;
+mod kgm;
+mod kwm;
+#[cfg(feature = "serde")]
+mod metadata;
+mod qmc;
+#[cfg(feature = "std")]
+mod reader;
+mod slice;
+
+#[cfg(feature = "tokio")]
+pub use async_reader::{from_async_reader, parse_header_async, AsyncNCMReader};
+pub use builder::NCMBuilder;
+#[cfg(feature = "serde")]
+pub use cache::CacheInfo;
+#[cfg(feature = "std")]
+pub use cache::CacheReader;
+pub use cache::{decrypt_cache_in_place, CACHE_KEY};
+#[cfg(all(feature = "std", feature = "serde"))]
+pub use decryptor::EncryptedTrackExt;
+#[cfg(feature = "std")]
+pub use decryptor::{
+    CacheDecryptor, Decryptor, DecryptorKind, EncryptedTrack, KGMDecryptor,
+    KWMDecryptor, NCMDecryptor, QMCDecryptor, Registry,
+};
+pub use format::{AudioFormat, ImageFormat};
+#[cfg(feature = "std")]
+pub use header::{parse_header, parse_header_skip_image, NCMHeader};
+#[cfg(feature = "std")]
+pub use kgm::KGMReader;
+pub use kgm::{KGMFile, KGMFormat, KGMHeader, KGMMask, KGM_MAGIC, VPR_MAGIC};
+#[cfg(feature = "std")]
+pub use kwm::KWMReader;
+pub use kwm::{KWMFile, KWMHeader, KWM_MAGIC, KWM_MAGIC_OLD};
+#[cfg(feature = "serde")]
+pub use metadata::{DJMetadata, NCMMetadata};
+#[cfg(feature = "std")]
+pub use qmc::QMCReader;
+pub use qmc::{decrypt_qmc_in_place, QMCFile, QMCFormat};
+#[cfg(feature = "std")]
+pub use reader::NCMReader;
+pub use slice::{from_slice, NCMFileRef};
+
+/// The AES-128 key of the RC4 key segment.
+const CORE_KEY: &[u8; 16] = b"hzHRAmso5kInbaxW";
+/// The AES-128 key of the metadata segment.
+const META_KEY: &[u8; 16] = b"#14ljk_!\\]&0U<'(";
+
 /// An error type represents all the possible errors.
-#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Error, Debug)]
 pub enum ParseError {
-    #[error("The ncm file ends unexpectedly.")]
+    #[error(
+        "The ncm file ends unexpectedly while reading {segment} at byte {offset}. \
+         [expected: {expected} bytes, available: {available} bytes]"
+    )]
     /// The *.ncm file ends unexpectedly while inisializing [`NCMFile`]
     /// with [`from_iter()`] or [`from_reader()`]
-    EndOfFile,
+    EndOfFile {
+        /// The segment being read.
+        segment: Segment,
+        /// The position in the ncm file where the missing data begins.
+        offset: u64,
+        /// The number of bytes expected from `offset`.
+        expected: u64,
+        /// The number of bytes actually available from `offset`.
+        available: u64,
+    },
+    #[cfg(feature = "std")]
+    #[error("Failed to read {segment} of the ncm file at byte {offset}.")]
+    /// The underlying reader returns an error other than reaching the end.
+    Io {
+        /// The segment being read.
+        segment: Segment,
+        /// The position in the ncm file where the error occurs.
+        offset: u64,
+        /// The error returned by the underlying reader.
+        #[source]
+        source: io::Error,
+    },
     #[error("The ncm file header does not match \"CTENFDAM\".")]
     /// The ncm file header does not match \"CTENFDAM\",
     /// which indicates the input file may not be ncm format.
@@ -101,7 +194,85 @@ pub enum ParseError {
     DecryptMetadataFailed,
     #[error("Failed parsing ncm metadata. [{0}]")]
     /// Failed to parse the JSON format metadata into struct.
-    ParseMetadataFailed(&'static str),
+    ParseMetadataFailed(String),
+    #[error("Unsupported cipher. [version: {version}, key slot: {slot}]")]
+    /// The file is encrypted with a cipher not supported yet, such as the
+    /// older Kugou Music files opened without the [`KGMMask`].
+    UnsupportedCrypto {
+        /// The version of the cipher.
+        version: u32,
+        /// The slot of the key.
+        slot: u32,
+    },
+    #[error("The mask table ends at byte {len} of the audio data.")]
+    /// The [`KGMMask`] is shorter than the audio data of the file.
+    MaskTooShort {
+        /// The length of the mask table.
+        len: u64,
+    },
+}
+
+/// The segments of an ncm file, used to point out where an error occurs.
+/// See [`NCMHeader`] for the layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    /// The "CTENFDAM" header and 2 bytes gap following it.
+    Header,
+    /// The AES-128 encrypted RC4 key, including its length.
+    Key,
+    /// The encrypted metadata, including its length.
+    Metadata,
+    /// The CRC32, an unused byte and the size of the image frame.
+    Gap,
+    /// The cover image, including its length and the padding after it.
+    Image,
+    /// The encrypted music data.
+    Music,
+}
+
+impl core::fmt::Display for Segment {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Segment::Header => "header",
+            Segment::Key => "RC4 key",
+            Segment::Metadata => "metadata",
+            Segment::Gap => "gap",
+            Segment::Image => "image",
+            Segment::Music => "music",
+        })
+    }
+}
+
+/// The kind of the metadata, told by the prefix of the decrypted metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataKind {
+    /// The metadata segment is empty, which is found in some old files.
+    None,
+    /// Starts with `music:`, the metadata of a song, see [`NCMMetadata`].
+    Music,
+    /// Starts with `dj:`, the metadata of a radio or podcast program, with
+    /// the song nested in `mainMusic`, see [`DJMetadata`].
+    Dj,
+}
+
+/// Read exactly `N` bytes from `iter`. `offset` is the position of `iter`
+/// in the ncm file, which moves forward after reading.
+fn read_chunk_iter<T, const N: usize>(
+    iter: &mut T,
+    segment: Segment,
+    offset: &mut u64,
+) -> Result<[u8; N], ParseError>
+where
+    T: Iterator<Item = u8>,
+{
+    let chunk = iter.next_chunk::<N>().map_err(|rest| EndOfFile {
+        segment,
+        offset: *offset,
+        expected: N as u64,
+        available: rest.len() as u64,
+    })?;
+    *offset += N as u64;
+    Ok(chunk)
 }
 
 /// A wrapped function for reading data
@@ -111,32 +282,73 @@ pub enum ParseError {
 /// |        segment_len         |         segment_data        |
 /// |  length_of_encrypted_data  |   encrypted_data_with_salt  |
 /// +----------------------------+-----------------------------+
-fn read_segment_iter<T>(iter: &mut T, salt: u8) -> Option<Vec<u8>>
+fn read_segment_iter<T>(
+    iter: &mut T,
+    salt: u8,
+    segment: Segment,
+    offset: &mut u64,
+) -> Result<Vec<u8>, ParseError>
 where
     T: Iterator<Item = u8> + Clone,
 {
-    let seg_len = u32::from_le_bytes(iter.next_chunk::<4>().ok()?) as usize;
-    let seg_data = iter.clone().take(seg_len).map(|x| x ^ salt).collect();
-    iter.advance_by(seg_len).ok()?;
-    Some(seg_data)
+    let seg_len =
+        u32::from_le_bytes(read_chunk_iter(iter, segment, offset)?) as usize;
+    let seg_data: Vec<u8> = iter.clone().take(seg_len).map(|x| x ^ salt).collect();
+    iter.advance_by(seg_len).map_err(|_| EndOfFile {
+        segment,
+        offset: *offset,
+        expected: seg_len as u64,
+        available: seg_data.len() as u64,
+    })?;
+    *offset += seg_len as u64;
+    Ok(seg_data)
 }
 
-/// A wrapped function for reading data
-/// +----------------------------------------------------------+
-/// |                         segment                          |
-/// +----------------------------+-----------------------------+
-/// |        segment_len         |         segment_data        |
-/// |  length_of_encrypted_data  |   encrypted_data_with_salt  |
-/// +----------------------------+-----------------------------+
-fn read_segment_reader<R: Read>(reader: &mut R, salt: u8) -> Option<Vec<u8>> {
-    let mut seg_len: [u8; 4] = Default::default();
-    reader.read_exact(&mut seg_len).ok()?;
-    let seg_len = u32::from_le_bytes(seg_len);
-
-    let mut seg_data: Vec<u8> = vec![0; seg_len as usize];
-    reader.read_exact(&mut seg_data).ok()?;
-    seg_data.iter_mut().for_each(|x| *x ^= salt);
-    Some(seg_data)
+#[cfg(feature = "std")]
+/// Read exactly `len` bytes from `reader`. `offset` is the position of
+/// `reader` in the ncm file, which moves forward after reading.
+fn read_exact_reader<R: Read>(
+    reader: &mut R,
+    len: u64,
+    segment: Segment,
+    offset: &mut u64,
+) -> Result<Vec<u8>, ParseError> {
+    // Don't allocate `len` bytes in advance, which may be a huge number
+    // read from a corrupted file.
+    let mut data = Vec::new();
+    let result = reader.by_ref().take(len).read_to_end(&mut data);
+    check_read_result(result, &data, len, segment, offset)?;
+    Ok(data)
+}
+
+#[cfg(feature = "std")]
+/// Turn the result of reading `len` bytes into `data` to [`ParseError`].
+/// `offset` is the position of the reader in the ncm file, which moves
+/// forward if the reading succeeds.
+fn check_read_result(
+    result: io::Result<usize>,
+    data: &[u8],
+    len: u64,
+    segment: Segment,
+    offset: &mut u64,
+) -> Result<(), ParseError> {
+    if let Err(source) = result {
+        return Err(Io {
+            segment,
+            offset: *offset + data.len() as u64,
+            source,
+        });
+    }
+    if (data.len() as u64) < len {
+        return Err(EndOfFile {
+            segment,
+            offset: *offset,
+            expected: len,
+            available: data.len() as u64,
+        });
+    }
+    *offset += len;
+    Ok(())
 }
 
 /// Parse the ncm file with iterator. Recommended if you have an ncm file
@@ -153,23 +365,43 @@ pub fn from_iter<T>(mut iter: T) -> Result<NCMFile, ParseError>
 where
     T: Iterator<Item = u8> + Clone,
 {
-    if iter.next_chunk::<10>().map_err(|_| EndOfFile)?[0..8] != *b"CTENFDAM" {
+    let mut offset = 0;
+    let ncm_header: [u8; 10] =
+        read_chunk_iter(&mut iter, Segment::Header, &mut offset)?;
+    if ncm_header[0..8] != *b"CTENFDAM" {
         return Err(InvalidHeader);
     }
-    let rc4_key = read_segment_iter(&mut iter, 0x64).ok_or(EndOfFile)?;
-    let metadata = read_segment_iter(&mut iter, 0x63).ok_or(EndOfFile)?;
-    let mut iter = iter.skip(9);
-    let image = read_segment_iter(&mut iter, 0).ok_or(EndOfFile)?;
+    let rc4_key = read_segment_iter(&mut iter, 0x64, Segment::Key, &mut offset)?;
+    let metadata =
+        read_segment_iter(&mut iter, 0x63, Segment::Metadata, &mut offset)?;
+    let crc32 = read_chunk_iter(&mut iter, Segment::Gap, &mut offset)?;
+    let crc32 = u32::from_le_bytes(crc32);
+    let [_, image_frame_len @ ..]: [u8; 5] =
+        read_chunk_iter(&mut iter, Segment::Gap, &mut offset)?;
+    let image_frame_len = u32::from_le_bytes(image_frame_len);
+    let image = read_segment_iter(&mut iter, 0, Segment::Image, &mut offset)?;
+    let padding = image_frame_len.saturating_sub(image.len() as u32) as usize;
+    iter.advance_by(padding).map_err(|rest| EndOfFile {
+        segment: Segment::Image,
+        offset,
+        expected: padding as u64,
+        available: (padding - rest.get()) as u64,
+    })?;
     let music = iter.collect();
     Ok(NCMFile {
         is_decrypted_flags: 0,
+        metadata_kind: MetadataKind::None,
         rc4_key,
         metadata,
+        decrypted_metadata: Vec::new(),
+        crc32,
+        image_frame_len,
         image,
         music,
     })
 }
 
+#[cfg(feature = "std")]
 /// Parse the ncm file with reader. Recommended if you have an ncm file
 /// opened from [File](std::fs::File).
 /// # Example
@@ -178,59 +410,60 @@ where
 /// let parsed_ncm_file = ncm_parser::from_reader(std::fs::File::open("xxx.ncm").unwrap()).unwrap();
 /// ```
 pub fn from_reader<R: Read>(mut reader: R) -> Result<NCMFile, ParseError> {
-    let mut ncm_header: [u8; 10] = Default::default();
-    reader.read_exact(&mut ncm_header).map_err(|_| EndOfFile)?;
-    if ncm_header[0..8] != *b"CTENFDAM" {
-        return Err(InvalidHeader);
-    }
-    let rc4_key = read_segment_reader(&mut reader, 0x64).ok_or(EndOfFile)?;
-    let metadata = read_segment_reader(&mut reader, 0x63).ok_or(EndOfFile)?;
-    reader.read_exact(&mut [0; 9]).map_err(|_| EndOfFile)?;
-    let image = read_segment_reader(&mut reader, 0).ok_or(EndOfFile)?;
+    let header = parse_header(&mut reader)?;
     let mut music = Vec::new();
-    reader.read_to_end(&mut music).map_err(|_| EndOfFile)?;
+    if let Err(source) = reader.read_to_end(&mut music) {
+        return Err(Io {
+            segment: Segment::Music,
+            offset: header.music_offset() + music.len() as u64,
+            source,
+        });
+    }
     Ok(NCMFile {
         is_decrypted_flags: 0,
-        rc4_key,
-        metadata,
-        image,
+        metadata_kind: MetadataKind::None,
+        rc4_key: header.rc4_key,
+        metadata: header.metadata,
+        decrypted_metadata: Vec::new(),
+        crc32: header.crc32,
+        image_frame_len: header.image_frame_len,
+        image: header.image,
         music,
     })
 }
 
-/// A struct contains all the data parsed from the ncm file.
+/// The keystream of the modified Rivest Cipher 4 used by ncm files.
+/// The n-th byte of the music is XORed with the `n % 256`-th byte of
+/// the keystream, so only 256 bytes need to be kept in memory.
+///
+/// Get it from [`NCMFile::get_key()`] or build it yourself, then decrypt
+/// the music with [`decrypt_music_in_place()`].
 #[derive(Debug, Clone)]
-pub struct NCMFile {
-    is_decrypted_flags: u8,
-    rc4_key: Vec<u8>,
-    metadata: Vec<u8>,
-    image: Vec<u8>,
-    music: Vec<u8>,
-}
-
-impl NCMFile {
-    /// Get music. Usually in MP3 or FLAC format.
-    /// This function contains the decrypting precedure if calling the first time,
-    /// and directly return the decrypted data after first-time calling.
-    pub fn get_music(&mut self) -> Result<&Vec<u8>, ParseError> {
-        if self.is_decrypted_flags & 0b0000_0001 != 0 {
-            return Ok(&self.music);
-        }
-        // The music data is not decrypted now.
-        self.is_decrypted_flags |= 0b0000_0001;
+pub struct NCMKey([u8; 256]);
 
+impl NCMKey {
+    /// Build the keystream from the AES-128 encrypted RC4 key segment,
+    /// which is already XORed with `0x64`.
+    pub fn new(rc4_key: &[u8]) -> Result<Self, ParseError> {
         // Decrypt RC4 key with AES-128
-        let rc4_key = Aes128Dec::new(b"hzHRAmso5kInbaxW".into())
-            .decrypt_padded::<Pkcs7>(&mut self.rc4_key)
+        let mut rc4_key = rc4_key.to_vec();
+        let rc4_key = Aes128Dec::new(CORE_KEY.into())
+            .decrypt_padded::<Pkcs7>(&mut rc4_key)
             .map_err(|_| DecryptRC4KeyFailed)?;
         if !rc4_key.starts_with(b"neteasecloudmusic") {
             return Err(DecryptRC4KeyFailed);
         }
-        let rc4_key = rc4_key[17..].iter().cycle();
+        Ok(Self::from_rc4_key(&rc4_key[17..]))
+    }
+
+    /// Build the keystream from the decrypted RC4 key
+    /// without the `neteasecloudmusic` prefix.
+    pub fn from_rc4_key(rc4_key: &[u8]) -> Self {
+        let rc4_key = rc4_key.iter().cycle();
 
         // Decrypt Music with modified Rivest Cipher 4
         // RC4-RSA
-        let mut rc4_sbox: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut rc4_sbox: [u8; 256] = core::array::from_fn(|i| i as u8);
 
         let mut j: u8 = 0;
         for (i, key) in (0..=255).zip(rc4_key) {
@@ -239,59 +472,176 @@ impl NCMFile {
         }
 
         // RC4-PRGA but no swap and iteration
-        let out_stream = std::array::from_fn::<u8, 256, _>(|i| {
+        let out_stream = core::array::from_fn::<u8, 256, _>(|i| {
             // i as u8 as usize == i & 0xff
             // Would too many 'as' affect performance?
             let i = i + 1;
             let j = rc4_sbox[i as u8 as usize] as usize;
             let k = rc4_sbox[(i + j) as u8 as usize] as usize;
             return rc4_sbox[(j + k) as u8 as usize];
-        })
-        .into_iter()
-        .cycle();
+        });
+
+        Self(out_stream)
+    }
+
+    /// Decrypt (or encrypt) `buf` in place, where `offset` is the position
+    /// of `buf[0]` in the music data.
+    fn apply(&self, buf: &mut [u8], offset: u64) {
+        let out_stream = self.0.iter().cycle().skip((offset % 256) as usize);
 
         // The compiler has done the SIMD optimization here.
-        self.music
-            .iter_mut()
+        buf.iter_mut()
             .zip(out_stream)
             .for_each(|(x, key)| *x ^= key);
+    }
+}
+
+/// Decrypt (or encrypt) the music data in `buf` in place, where `offset` is
+/// the position of `buf[0]` in the music data. Useful for decrypting a large
+/// file chunk by chunk into reused buffers.
+/// # Example
+/// ```no_run
+/// let data = std::fs::read("xxx.ncm").unwrap();
+/// let ncm_file = ncm_parser::from_slice(&data).unwrap();
+/// let key = ncm_file.get_key().unwrap();
+///
+/// let mut buf = [0; 0x8000];
+/// let mut offset = 0;
+/// for chunk in ncm_file.get_music_unchecked().chunks(buf.len()) {
+///     let buf = &mut buf[..chunk.len()];
+///     buf.copy_from_slice(chunk);
+///     ncm_parser::decrypt_music_in_place(buf, &key, offset);
+///     offset += chunk.len() as u64;
+/// }
+/// ```
+pub fn decrypt_music_in_place(buf: &mut [u8], key: &NCMKey, offset: u64) {
+    key.apply(buf, offset);
+}
+
+/// Decrypt the metadata segment with BASE64 and AES-128, returns the kind
+/// and the JSON format metadata without the prefix, which is empty if the
+/// metadata segment is empty.
+fn decrypt_metadata(metadata: &[u8]) -> Result<(MetadataKind, Vec<u8>), ParseError> {
+    if metadata.is_empty() {
+        return Ok((MetadataKind::None, Vec::new()));
+    }
+    if !metadata.starts_with(b"163 key(Don't modify):") {
+        return Err(DecryptMetadataFailed);
+    }
+    // Decrypt metadata with BASE64
+    let mut metadata = base64dec
+        .decode(&metadata[22..])
+        .map_err(|_| DecryptMetadataFailed)?;
+    // Decrypt metadata with AES-128
+    let metadata = Aes128Dec::new(META_KEY.into())
+        .decrypt_padded::<Pkcs7>(&mut metadata)
+        .map_err(|_| DecryptMetadataFailed)?;
+    let (kind, metadata) = if let Some(metadata) = metadata.strip_prefix(b"music:") {
+        (MetadataKind::Music, metadata)
+    } else if let Some(metadata) = metadata.strip_prefix(b"dj:") {
+        (MetadataKind::Dj, metadata)
+    } else {
+        return Err(DecryptMetadataFailed);
+    };
+    Ok((kind, metadata.to_vec()))
+}
+
+/// A struct contains all the data parsed from the ncm file.
+#[derive(Debug, Clone)]
+pub struct NCMFile {
+    is_decrypted_flags: u8,
+    metadata_kind: MetadataKind,
+    rc4_key: Vec<u8>,
+    /// The metadata segment, kept after decryption for
+    /// [`EncryptedTrack::raw_metadata()`](crate::EncryptedTrack::raw_metadata()).
+    metadata: Vec<u8>,
+    decrypted_metadata: Vec<u8>,
+    crc32: u32,
+    image_frame_len: u32,
+    image: Vec<u8>,
+    music: Vec<u8>,
+}
+
+impl NCMFile {
+    /// Get music. Usually in MP3 or FLAC format.
+    /// This function contains the decrypting precedure if calling the first time,
+    /// and directly return the decrypted data after first-time calling.
+    pub fn get_music(&mut self) -> Result<&Vec<u8>, ParseError> {
+        if self.is_decrypted_flags & 0b0000_0001 != 0 {
+            return Ok(&self.music);
+        }
+        // The music data is not decrypted now.
+        NCMKey::new(&self.rc4_key)?.apply(&mut self.music, 0);
+        self.is_decrypted_flags |= 0b0000_0001;
 
         return Ok(&self.music);
     }
 
+    /// Decrypt the music into `dst` without touching the music kept in
+    /// [`NCMFile`], returns the number of bytes decrypted, which is the
+    /// smaller one of the music length and `dst.len()`.
+    pub fn decrypt_music_into(&self, dst: &mut [u8]) -> Result<usize, ParseError> {
+        let len = dst.len().min(self.music.len());
+        dst[..len].copy_from_slice(&self.music[..len]);
+        // The music data might have been decrypted by `get_music()`.
+        if self.is_decrypted_flags & 0b0000_0001 == 0 {
+            self.get_key()?.apply(&mut dst[..len], 0);
+        }
+        Ok(len)
+    }
+
+    /// Decrypt the RC4 key, which can be used to decrypt the music with
+    /// [`decrypt_music_in_place()`].
+    pub fn get_key(&self) -> Result<NCMKey, ParseError> {
+        NCMKey::new(&self.rc4_key)
+    }
+
+    /// Detect the format of the music from its first bytes, which is more
+    /// reliable than the `format` in the metadata. Returns `None` if unknown.
+    pub fn audio_format(&self) -> Result<Option<AudioFormat>, ParseError> {
+        let mut header = [0; AudioFormat::SNIFF_LEN];
+        let len = self.decrypt_music_into(&mut header)?;
+        // Decrypt the bytes after the ID3v2 tag as well, if any.
+        let sniff_len = AudioFormat::sniff_len(&header[..len]);
+        if sniff_len <= len {
+            return Ok(AudioFormat::from_header(&header[..len]));
+        }
+        let mut header = vec![0; sniff_len.min(self.music.len())];
+        let len = self.decrypt_music_into(&mut header)?;
+        Ok(AudioFormat::from_header(&header[..len]))
+    }
+
     /// Get cover image. Usually in PNG or JPEG format.
     /// Same as [`get_image_unchecked()`](NCMFile::get_image_unchecked()).
     pub fn get_image(&self) -> Result<&Vec<u8>, !> {
         Ok(&self.image)
     }
 
+    /// Detect the format of the cover image from its first bytes. Returns
+    /// `None` if the file has no cover image or the format is unknown.
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        ImageFormat::from_header(&self.image)
+    }
+
     /// Get metadata.
     /// This function contains the decrypting precedure if calling the first time,
     /// and directly return the decrypted data after first-time calling.
     pub fn get_metadata(&mut self) -> Result<&Vec<u8>, ParseError> {
         if self.is_decrypted_flags & 0b0000_0010 != 0 {
-            return Ok(&self.metadata);
+            return Ok(&self.decrypted_metadata);
         }
         // The metadata is not decrypted now.
+        (self.metadata_kind, self.decrypted_metadata) =
+            decrypt_metadata(&self.metadata)?;
         self.is_decrypted_flags |= 0b0000_0010;
 
-        if !self.metadata.starts_with(b"163 key(Don't modify):") {
-            return Err(DecryptMetadataFailed);
-        }
-        // Decrypt metadata with BASE64
-        let mut metadata = base64dec
-            .decode(&self.metadata[22..])
-            .map_err(|_| DecryptMetadataFailed)?;
-        // Decrypt metadata with AES-128
-        let metadata = Aes128Dec::new(b"#14ljk_!\\]&0U<'(".into())
-            .decrypt_padded::<Pkcs7>(&mut metadata)
-            .map_err(|_| DecryptMetadataFailed)?;
-        if !metadata.starts_with(b"music:") {
-            return Err(DecryptMetadataFailed);
-        }
-        self.metadata = metadata[6..].to_vec();
+        Ok(&self.decrypted_metadata)
+    }
 
-        Ok(&self.metadata)
+    /// Get the kind of the metadata, which is decrypted if it has not been.
+    pub fn get_metadata_kind(&mut self) -> Result<MetadataKind, ParseError> {
+        self.get_metadata()?;
+        Ok(self.metadata_kind)
     }
 
     /// Directly get cover image. Usually in PNG or JPEG format.
@@ -309,164 +659,36 @@ impl NCMFile {
     /// Directly get metadata.
     /// The metadata is not decrypted if [`get_metadata()`](NCMFile::get_metadata()) has never been called.
     pub fn get_metadata_unchecked(&self) -> &Vec<u8> {
-        &self.metadata
+        match self.is_decrypted_flags & 0b0000_0010 {
+            0 => &self.metadata,
+            _ => &self.decrypted_metadata,
+        }
     }
 
-    #[cfg(feature = "serde_json")]
-    /// Parse the JSON format metadata into struct.
-    pub fn get_parsed_metadata(&mut self) -> Result<NCMMetadata, ParseError> {
-        let metadata = self.get_metadata()?;
-        #[allow(deprecated)]
-        return NCMMetadata::new(metadata);
+    /// The CRC32 stored in front of the image segment. It is not verified,
+    /// since what the clients calculate it from is unknown.
+    pub fn crc32(&self) -> u32 {
+        self.crc32
     }
-}
 
-#[cfg(feature = "serde_json")]
-#[derive(Debug, Clone)]
-#[allow(missing_docs)]
-/// A struct contains all the JSON values in metadata.
-pub struct NCMMetadata {
-    /// music_id might not be a number.
-    pub music_id: String,
-    pub music_name: String,
-    pub artists: Vec<(String, u64)>,
-    pub album_id: u64,
-    pub album_name: String,
-    pub album_pic_doc_id: u64,
-    pub album_pic_url: String,
-    pub bitrate: u64,
-    pub mp3_doc_id: Option<String>,
-    pub duration: u64,
-    pub mv_id: u64,
-    pub alias: Vec<String>,
-    pub trans_names: Vec<String>,
-    pub format: String,
-    pub fee: Option<u64>,
-    pub flag: Option<u64>,
-}
+    /// The space reserved for the cover image, which may be larger than
+    /// the length of the image. The music data starts after the reserved
+    /// space. Old files may store a smaller value, which is ignored then.
+    pub fn image_frame_len(&self) -> u32 {
+        self.image_frame_len
+    }
 
-#[cfg(feature = "serde_json")]
-impl NCMMetadata {
-    #[deprecated(
-        since = "0.2.0",
-        note = "Use `NCMFile::get_parsed_metadata()` instead."
-    )]
+    #[cfg(feature = "serde")]
     /// Parse the JSON format metadata into struct.
-    /// Returns [`None`] if parsing failed.
-    pub fn new(metadata: &[u8]) -> Result<Self, ParseError> {
-        use std::str::FromStr;
-
-        let json: serde_json::Value = serde_json::from_slice(metadata)
-            .map_err(|_| ParseMetadataFailed("Cannot read the ncm metadata."))?;
-
-        let music_id = json["musicId"]
-            .as_str()
-            .map(|x| x.to_string())
-            .unwrap_or_else(|| json["musicId"].to_string());
-
-        let music_name = json["musicName"]
-            .as_str()
-            .ok_or(ParseMetadataFailed("Failed parsing [musicName]."))?
-            .to_string();
-
-        let artists: Vec<_> = json["artist"]
-            .as_array()
-            .ok_or(ParseMetadataFailed("Failed parsing [artist]."))?
-            .into_iter()
-            .map(|artist| {
-                let [name, id] = &artist.as_array()?[0..2] else {
-                    return None;
-                };
-                let name = name.as_str()?.to_string();
-                let id = id.as_u64().or_else(|| u64::from_str(id.as_str()?).ok())?;
-                return Some((name, id));
-            })
-            .try_collect()
-            .ok_or(ParseMetadataFailed("Failed parsing [artist]."))?;
-
-        let album_id = json["albumId"]
-            .as_u64()
-            .or_else(|| u64::from_str(json["albumId"].as_str()?).ok())
-            .ok_or(ParseMetadataFailed("Failed parsing [albumId]."))?;
-
-        let album_name = json["album"]
-            .as_str()
-            .ok_or(ParseMetadataFailed("Failed parsing [album]."))?
-            .to_string();
-
-        let album_pic_doc_id = json["albumPicDocId"]
-            .as_u64()
-            .or_else(|| u64::from_str(json["albumPicDocId"].as_str()?).ok())
-            .ok_or(ParseMetadataFailed("Failed parsing [albumPicDocId]."))?;
-
-        let album_pic_url = json["albumPic"]
-            .as_str()
-            .ok_or(ParseMetadataFailed("Failed parsing [albumPic]."))?
-            .to_string();
-
-        let bitrate = json["bitrate"]
-            .as_u64()
-            .ok_or(ParseMetadataFailed("Failed parsing [bitrate]."))?;
-
-        let mp3_doc_id = json["mp3DocId"].as_str().map(|x| x.to_string());
-
-        let duration = json["duration"]
-            .as_u64()
-            .ok_or(ParseMetadataFailed("Failed parsing [duration]."))?;
-
-        let mv_id = json["mvId"]
-            .as_u64()
-            .or_else(|| u64::from_str(json["mvId"].as_str()?).ok())
-            .unwrap_or_default();
-
-        let alias: Vec<_> = json["alias"]
-            .as_array()
-            .ok_or(ParseMetadataFailed("Failed parsing [alias]."))?
-            .into_iter()
-            .map(|x| x.as_str().map(|x| x.to_string()))
-            .try_collect()
-            .ok_or(ParseMetadataFailed("Failed parsing [alias]."))?;
-
-        let trans_names: Vec<_> = json["transNames"]
-            .as_array()
-            .ok_or(ParseMetadataFailed("Failed parsing [transNames]."))?
-            .into_iter()
-            .map(|x| x.as_str().map(|x| x.to_string()))
-            .try_collect()
-            .ok_or(ParseMetadataFailed("Failed parsing [transNames]."))?;
-
-        let format = json["format"]
-            .as_str()
-            .ok_or(ParseMetadataFailed("Failed parsing [format]."))?
-            .to_string();
-
-        let fee = json["fee"].as_u64();
-
-        let mut flag = json["flag"].as_u64();
-        if flag == None {
-            let privilege = json["privilege"].as_object();
-            if let Some(inner_flag) = privilege {
-                flag = inner_flag["flag"].as_u64();
-            }
-        }
+    /// For radio or podcast programs, the song in `mainMusic` is returned.
+    pub fn get_parsed_metadata(&mut self) -> Result<NCMMetadata, ParseError> {
+        let kind = self.get_metadata_kind()?;
+        parse_song(kind, &self.decrypted_metadata)
+    }
 
-        return Ok(Self {
-            music_name,
-            music_id,
-            artists,
-            album_name,
-            album_id,
-            album_pic_doc_id,
-            album_pic_url,
-            bitrate,
-            mp3_doc_id,
-            duration,
-            mv_id,
-            alias,
-            trans_names,
-            format,
-            fee,
-            flag,
-        });
+    #[cfg(feature = "serde")]
+    /// Parse the JSON format metadata of radio or podcast programs into struct.
+    pub fn get_parsed_dj_metadata(&mut self) -> Result<DJMetadata, ParseError> {
+        parse_json(self.get_metadata()?)
     }
 }
@@ -0,0 +1,436 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::de::{self, DeserializeOwned, IgnoredAny, SeqAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::MetadataKind;
+use crate::ParseError::{self, *};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(remote = "Self", rename_all = "camelCase")]
+#[allow(missing_docs)]
+/// A struct contains all the JSON values in metadata.
+///
+/// It can be (de)serialized with the original field names in the ncm file,
+/// such as `musicName` and `albumPicDocId`. Serializing it again writes
+/// back the original JSON, unless the fields are changed. A changed field
+/// is written in its own type, such as `musicId` as a string.
+pub struct NCMMetadata {
+    /// music_id might not be a number.
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub music_id: String,
+    pub music_name: String,
+    /// Stored as `[name, id]` pairs in the ncm file.
+    #[serde(rename = "artist", deserialize_with = "artists")]
+    pub artists: Vec<(String, u64)>,
+    #[serde(deserialize_with = "u64_or_string")]
+    pub album_id: u64,
+    #[serde(rename = "album")]
+    pub album_name: String,
+    #[serde(deserialize_with = "u64_or_string")]
+    pub album_pic_doc_id: u64,
+    #[serde(rename = "albumPic")]
+    pub album_pic_url: String,
+    pub bitrate: u64,
+    /// `None` if missing or not a string.
+    #[serde(default, deserialize_with = "string_or_none")]
+    pub mp3_doc_id: Option<String>,
+    pub duration: u64,
+    /// Zero if missing or invalid.
+    #[serde(default, deserialize_with = "u64_or_string_or_default")]
+    pub mv_id: u64,
+    pub alias: Vec<String>,
+    pub trans_names: Vec<String>,
+    pub format: String,
+    /// `None` if missing or not a non-negative integer.
+    #[serde(default, deserialize_with = "u64_or_none")]
+    pub fee: Option<u64>,
+    /// Read from `privilege.flag` if `flag` is missing or invalid.
+    /// Written to the top-level `flag` only if it's changed.
+    #[serde(flatten, deserialize_with = "flag::deserialize")]
+    pub flag: Option<u64>,
+    /// All the other fields not listed above, so that nothing is lost after
+    /// serializing the metadata again. The top-level `flag` is kept here as
+    /// it is, see [`flag`](NCMMetadata::flag).
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+    /// The original values of the fields which are converted when parsing,
+    /// see [`RAW_FIELDS`].
+    #[serde(skip)]
+    raw: Map<String, Value>,
+}
+
+/// The fields which might be of another type or invalid in the ncm file.
+const RAW_FIELDS: [&str; 7] = [
+    "musicId",
+    "artist",
+    "albumId",
+    "albumPicDocId",
+    "mp3DocId",
+    "mvId",
+    "fee",
+];
+
+impl<'de> Deserialize<'de> for NCMMetadata {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(d)?;
+        // The one derived with `remote = "Self"`.
+        let mut metadata =
+            NCMMetadata::deserialize(&value).map_err(de::Error::custom)?;
+        let Value::Object(mut map) = value else {
+            unreachable!("only a map can be deserialized into NCMMetadata");
+        };
+        for key in RAW_FIELDS {
+            if let Some(raw) = map.remove(key) {
+                metadata.raw.insert(key.to_string(), raw);
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+/// Write the original value of `key` if `value` is still the one parsed from
+/// it, otherwise write `value`. A missing field is left missing as long as
+/// `value` is the default.
+fn serialize_field<M, T, F>(
+    map: &mut M,
+    raw: &Map<String, Value>,
+    key: &str,
+    value: &T,
+    parse: F,
+) -> Result<(), M::Error>
+where
+    M: SerializeMap,
+    T: Serialize + PartialEq + Default,
+    F: FnOnce(&Value) -> Result<T, serde_json::Error>,
+{
+    match raw.get(key) {
+        Some(raw) if parse(raw).is_ok_and(|x| x == *value) => {
+            map.serialize_entry(key, raw)
+        }
+        None if *value == T::default() => Ok(()),
+        _ => map.serialize_entry(key, value),
+    }
+}
+
+impl Serialize for NCMMetadata {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let raw = &self.raw;
+        let mut map = s.serialize_map(None)?;
+        serialize_field(&mut map, raw, "musicId", &self.music_id, |x| {
+            string_or_number(x)
+        })?;
+        map.serialize_entry("musicName", &self.music_name)?;
+        serialize_field(&mut map, raw, "artist", &self.artists, |x| artists(x))?;
+        serialize_field(&mut map, raw, "albumId", &self.album_id, |x| {
+            u64_or_string(x)
+        })?;
+        map.serialize_entry("album", &self.album_name)?;
+        serialize_field(
+            &mut map,
+            raw,
+            "albumPicDocId",
+            &self.album_pic_doc_id,
+            |x| u64_or_string(x),
+        )?;
+        map.serialize_entry("albumPic", &self.album_pic_url)?;
+        map.serialize_entry("bitrate", &self.bitrate)?;
+        serialize_field(&mut map, raw, "mp3DocId", &self.mp3_doc_id, |x| {
+            string_or_none(x)
+        })?;
+        map.serialize_entry("duration", &self.duration)?;
+        serialize_field(&mut map, raw, "mvId", &self.mv_id, |x| {
+            u64_or_string_or_default(x)
+        })?;
+        map.serialize_entry("alias", &self.alias)?;
+        map.serialize_entry("transNames", &self.trans_names)?;
+        map.serialize_entry("format", &self.format)?;
+        serialize_field(&mut map, raw, "fee", &self.fee, |x| u64_or_none(x))?;
+        // Otherwise `flag` is the same as the one read from `extra`.
+        let flag_changed = self.flag != flag::read(&self.extra);
+        if flag_changed {
+            map.serialize_entry("flag", &self.flag)?;
+        }
+        for (key, value) in &self.extra {
+            if !(flag_changed && key == "flag") {
+                map.serialize_entry(key, value)?;
+            }
+        }
+        map.end()
+    }
+}
+
+impl NCMMetadata {
+    #[deprecated(
+        since = "0.2.0",
+        note = "Use `NCMFile::get_parsed_metadata()` instead."
+    )]
+    /// Parse the JSON format metadata into struct.
+    pub fn new(metadata: &[u8]) -> Result<Self, ParseError> {
+        parse_json(metadata)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(missing_docs)]
+/// A struct contains the JSON values in the metadata of radio or podcast
+/// programs, see [`MetadataKind::Dj`](crate::MetadataKind::Dj).
+///
+/// Only `mainMusic` is required, the missing fields are left empty or zero.
+pub struct DJMetadata {
+    /// program_id might not be a number.
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub program_id: String,
+    #[serde(default)]
+    pub program_name: String,
+    /// The song of the program.
+    pub main_music: NCMMetadata,
+    #[serde(default, deserialize_with = "u64_or_string_or_default")]
+    pub dj_id: u64,
+    #[serde(default)]
+    pub dj_name: String,
+    pub dj_avatar_url: Option<String>,
+    /// In milliseconds since the Unix epoch.
+    #[serde(default, deserialize_with = "u64_or_string_or_default")]
+    pub create_time: u64,
+    pub brand: Option<String>,
+    /// The episode number of the program in the radio.
+    #[serde(default, deserialize_with = "u64_or_string_or_default")]
+    pub serial: u64,
+    pub program_desc: Option<String>,
+    #[serde(default, deserialize_with = "u64_or_string_or_default")]
+    pub radio_id: u64,
+    #[serde(default)]
+    pub radio_name: String,
+    pub radio_category: Option<String>,
+    pub radio_desc: Option<String>,
+    /// All the other fields not listed above.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Parse the JSON format metadata into struct.
+pub(crate) fn parse_json<T: DeserializeOwned>(
+    metadata: &[u8],
+) -> Result<T, ParseError> {
+    serde_json::from_slice(metadata)
+        .map_err(|err| ParseMetadataFailed(err.to_string()))
+}
+
+/// Parse the metadata of the song, which is nested in `mainMusic` of
+/// radio or podcast programs.
+pub(crate) fn parse_song(
+    kind: MetadataKind,
+    metadata: &[u8],
+) -> Result<NCMMetadata, ParseError> {
+    match kind {
+        MetadataKind::Dj => parse_json(metadata).map(|dj: DJMetadata| dj.main_music),
+        _ => parse_json(metadata),
+    }
+}
+
+/// Accept both `"123"` and `123`.
+pub(crate) fn string_or_number<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<String, D::Error> {
+    struct StringOrNumber;
+
+    impl<'de> Visitor<'de> for StringOrNumber {
+        type Value = String;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a string or a number")
+        }
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+    }
+
+    d.deserialize_any(StringOrNumber)
+}
+
+/// Accept both `123` and `"123"`. Returns zero for anything else if `lossy`.
+struct U64OrString {
+    lossy: bool,
+}
+
+impl<'de> Visitor<'de> for U64OrString {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a non-negative integer or a string of it")
+    }
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+        Ok(v)
+    }
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+        match v.parse() {
+            Ok(v) => Ok(v),
+            Err(_) if self.lossy => Ok(0),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Str(v), &self)),
+        }
+    }
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<u64, E> {
+        match u64::try_from(v) {
+            Ok(v) => Ok(v),
+            Err(_) if self.lossy => Ok(0),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Signed(v), &self)),
+        }
+    }
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<u64, E> {
+        match self.lossy {
+            true => Ok(0),
+            false => Err(E::invalid_type(de::Unexpected::Float(v), &self)),
+        }
+    }
+    fn visit_unit<E: de::Error>(self) -> Result<u64, E> {
+        match self.lossy {
+            true => Ok(0),
+            false => Err(E::invalid_type(de::Unexpected::Unit, &self)),
+        }
+    }
+}
+
+fn u64_or_string<'de, D: Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+    d.deserialize_any(U64OrString { lossy: false })
+}
+
+pub(crate) fn u64_or_string_or_default<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<u64, D::Error> {
+    d.deserialize_any(U64OrString { lossy: true })
+}
+
+/// Accept anything, returns `None` unless it's a string.
+fn string_or_none<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(Value::deserialize(d)?.as_str().map(ToString::to_string))
+}
+
+/// Accept anything, returns `None` unless it's a non-negative integer.
+fn u64_or_none<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+    Ok(Value::deserialize(d)?.as_u64())
+}
+
+/// Read `[[name, id], ...]`, where `id` might be a string.
+fn artists<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<(String, u64)>, D::Error> {
+    struct Artist(String, u64);
+
+    impl<'de> Deserialize<'de> for Artist {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            d.deserialize_seq(ArtistVisitor)
+        }
+    }
+
+    struct ArtistVisitor;
+
+    impl<'de> Visitor<'de> for ArtistVisitor {
+        type Value = Artist;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an array of artist name and id")
+        }
+        fn visit_seq<A: SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> Result<Artist, A::Error> {
+            struct Id(u64);
+
+            impl<'de> Deserialize<'de> for Id {
+                fn deserialize<D: Deserializer<'de>>(
+                    d: D,
+                ) -> Result<Self, D::Error> {
+                    u64_or_string(d).map(Id)
+                }
+            }
+
+            let name = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            let Id(id) = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+            // Ignore the rest elements.
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            Ok(Artist(name, id))
+        }
+    }
+
+    let artists = Vec::<Artist>::deserialize(d)?;
+    Ok(artists
+        .into_iter()
+        .map(|Artist(name, id)| (name, id))
+        .collect())
+}
+
+/// `flag` is stored in `privilege.flag` by some clients.
+mod flag {
+    use alloc::string::String;
+    use core::fmt;
+
+    use serde::de::{IgnoredAny, MapAccess, Visitor};
+    use serde::Deserializer;
+    use serde_json::{Map, Value};
+
+    struct FlagVisitor;
+
+    impl<'de> Visitor<'de> for FlagVisitor {
+        type Value = Option<u64>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a map containing flag or privilege.flag")
+        }
+        fn visit_map<A: MapAccess<'de>>(
+            self,
+            mut map: A,
+        ) -> Result<Self::Value, A::Error> {
+            let mut flag = None;
+            let mut privilege_flag = None;
+            while let Some(key) = map.next_key::<String>()? {
+                match key.as_str() {
+                    "flag" => flag = map.next_value::<Value>()?.as_u64(),
+                    "privilege" => {
+                        privilege_flag = map
+                            .next_value::<Value>()?
+                            .get("flag")
+                            .and_then(Value::as_u64)
+                    }
+                    _ => {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                }
+            }
+            Ok(flag.or(privilege_flag))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Option<u64>, D::Error> {
+        // Visit the fields as a map, so that `privilege` is left for
+        // `NCMMetadata::extra`.
+        d.deserialize_map(FlagVisitor)
+    }
+
+    /// Read the flag from the fields kept in `NCMMetadata::extra` the same
+    /// way as [`deserialize()`].
+    pub fn read(extra: &Map<String, Value>) -> Option<u64> {
+        let flag = extra.get("flag").and_then(Value::as_u64);
+        flag.or_else(|| extra.get("privilege")?.get("flag")?.as_u64())
+    }
+}
@@ -0,0 +1,254 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom};
+
+#[cfg(feature = "std")]
+use crate::ParseError::{self, *};
+#[cfg(feature = "std")]
+use crate::Segment;
+
+/// The seed map of the static QMC cipher.
+const SEED_MAP: [[u8; 7]; 8] = [
+    [0x4a, 0xd6, 0xca, 0x90, 0x67, 0xf7, 0x52],
+    [0x5e, 0x95, 0x23, 0x9f, 0x13, 0x11, 0x7e],
+    [0x47, 0x74, 0x3d, 0x90, 0xaa, 0x3f, 0x51],
+    [0xc6, 0x09, 0xd5, 0x9f, 0xfa, 0x66, 0xf9],
+    [0xf3, 0xd6, 0xa1, 0x90, 0xa0, 0xf7, 0xf0],
+    [0x1d, 0x95, 0xde, 0x9f, 0x84, 0x11, 0xf4],
+    [0x0e, 0x74, 0xbb, 0x90, 0xbc, 0x3f, 0x92],
+    [0x00, 0x09, 0x5b, 0x9f, 0x62, 0x66, 0xa1],
+];
+
+/// The n-th byte of the music is XORed with
+/// `MASK_BOX[(m * m + 27) % 256]`, where `m = n % 0x7fff` if `n > 0x7fff`,
+/// otherwise `m = n`.
+static MASK_BOX: [u8; 256] = mask_box();
+
+/// Walk through [`SEED_MAP`] back and forth like the original cipher, and
+/// put the masks of the first `0x8000` bytes into [`MASK_BOX`], which is
+/// enough to fill every index used.
+const fn mask_box() -> [u8; 256] {
+    let mut mask_box = [0; 256];
+    let (mut x, mut y, mut dx) = (-1i32, 8usize, 1i32);
+    let mut offset = 0;
+    while offset < 0x8000 {
+        let mask = if x < 0 {
+            dx = 1;
+            y = (8 - y) % 8;
+            0xc3
+        } else if x > 6 {
+            dx = -1;
+            y = 7 - y;
+            0xd8
+        } else {
+            SEED_MAP[y][x as usize]
+        };
+        x += dx;
+        mask_box[(offset * offset + 27) & 0xff] = mask;
+        offset += 1;
+    }
+    mask_box
+}
+
+/// Decrypt (or encrypt) the QMC music data in `buf` in place, where `offset`
+/// is the position of `buf[0]` in the file.
+pub fn decrypt_qmc_in_place(buf: &mut [u8], offset: u64) {
+    for (x, offset) in buf.iter_mut().zip(offset..) {
+        let offset = match offset > 0x7fff {
+            true => offset % 0x7fff,
+            false => offset,
+        };
+        *x ^= MASK_BOX[(offset * offset + 27) as usize & 0xff];
+    }
+}
+
+/// The variants of QMC files encrypted with the static cipher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QMCFormat {
+    /// `.qmc0`, MP3 inside.
+    Qmc0,
+    /// `.qmc3`, MP3 inside.
+    Qmc3,
+    /// `.qmcflac`, FLAC inside.
+    QmcFlac,
+    /// `.qmcogg`, Ogg Vorbis inside.
+    QmcOgg,
+}
+
+impl QMCFormat {
+    /// Get the variant from the file extension without the leading dot,
+    /// which is case-insensitive.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "qmc0" => Some(Self::Qmc0),
+            "qmc3" => Some(Self::Qmc3),
+            "qmcflac" => Some(Self::QmcFlac),
+            "qmcogg" => Some(Self::QmcOgg),
+            _ => None,
+        }
+    }
+
+    /// The file extension of the decrypted music, such as `mp3`.
+    pub fn music_extension(&self) -> &'static str {
+        match self {
+            Self::Qmc0 | Self::Qmc3 => "mp3",
+            Self::QmcFlac => "flac",
+            Self::QmcOgg => "ogg",
+        }
+    }
+}
+
+/// A struct contains the whole QMC file, which is nothing but the
+/// encrypted music.
+/// # Example
+/// ```no_run
+/// use ncm_parser::{QMCFile, QMCFormat};
+///
+/// let data = std::fs::read("xxx.qmcflac").unwrap();
+/// let mut qmc_file = QMCFile::new(data, QMCFormat::QmcFlac);
+/// std::fs::write("xxx.flac", qmc_file.get_music().unwrap()).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct QMCFile {
+    is_decrypted: bool,
+    format: QMCFormat,
+    music: Vec<u8>,
+}
+
+impl QMCFile {
+    /// Create from the content of a QMC file. Nothing is decrypted until asked.
+    pub fn new(data: Vec<u8>, format: QMCFormat) -> Self {
+        Self {
+            is_decrypted: false,
+            format,
+            music: data,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    /// Read the whole QMC file from `reader`.
+    pub fn from_reader<R: Read>(
+        mut reader: R,
+        format: QMCFormat,
+    ) -> Result<Self, ParseError> {
+        let mut music = Vec::new();
+        if let Err(source) = reader.read_to_end(&mut music) {
+            return Err(Io {
+                segment: Segment::Music,
+                offset: music.len() as u64,
+                source,
+            });
+        }
+        Ok(Self::new(music, format))
+    }
+
+    /// Get music. Usually in MP3, FLAC or Ogg format, see
+    /// [`format()`](QMCFile::format()).
+    /// This function contains the decrypting precedure if calling the first time,
+    /// and directly return the decrypted data after first-time calling.
+    pub fn get_music(&mut self) -> Result<&Vec<u8>, !> {
+        if !self.is_decrypted {
+            self.is_decrypted = true;
+            decrypt_qmc_in_place(&mut self.music, 0);
+        }
+        Ok(&self.music)
+    }
+
+    /// Directly get music.
+    /// The music data is not decrypted if [`get_music()`](QMCFile::get_music()) has never been called.
+    pub fn get_music_unchecked(&self) -> &Vec<u8> {
+        &self.music
+    }
+
+    /// Decrypt the music into `dst` without touching the music kept in
+    /// [`QMCFile`], returns the number of bytes decrypted, which is the
+    /// smaller one of the music length and `dst.len()`.
+    pub fn decrypt_music_into(&self, dst: &mut [u8]) -> usize {
+        let len = dst.len().min(self.music.len());
+        dst[..len].copy_from_slice(&self.music[..len]);
+        if !self.is_decrypted {
+            decrypt_qmc_in_place(&mut dst[..len], 0);
+        }
+        len
+    }
+
+    /// The variant of the QMC file.
+    pub fn format(&self) -> QMCFormat {
+        self.format
+    }
+}
+
+/// A streaming QMC parser, which decrypts the music on the fly through
+/// [`Read`]. Seeking is supported if the underlying reader implements
+/// [`Seek`].
+/// # Example
+/// ```no_run
+/// use std::fs::File;
+/// use ncm_parser::{QMCFormat, QMCReader};
+///
+/// let file = File::open("xxx.qmc0").unwrap();
+/// let mut reader = QMCReader::new(file, QMCFormat::Qmc0);
+/// let mut music = File::create("xxx.mp3").unwrap();
+/// std::io::copy(&mut reader, &mut music).unwrap();
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct QMCReader<R> {
+    inner: R,
+    format: QMCFormat,
+    pos: u64,
+}
+
+#[cfg(feature = "std")]
+impl<R> QMCReader<R> {
+    /// Wrap the reader of a QMC file, which should be at the beginning of
+    /// the file. Nothing is read until asked.
+    pub fn new(reader: R, format: QMCFormat) -> Self {
+        Self {
+            inner: reader,
+            format,
+            pos: 0,
+        }
+    }
+
+    /// The variant of the QMC file.
+    pub fn format(&self) -> QMCFormat {
+        self.format
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// Reading from the underlying reader directly would mess up the
+    /// decryption of the rest music data.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `QMCReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for QMCReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        decrypt_qmc_in_place(&mut buf[..len], self.pos);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Seek> Seek for QMCReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,182 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::{
+    parse_header, ImageFormat, MetadataKind, NCMHeader, NCMKey, ParseError,
+};
+#[cfg(feature = "serde")]
+use crate::{DJMetadata, NCMMetadata};
+
+/// A streaming ncm parser. The key, metadata and image segments are parsed
+/// when constructing, and the music data is decrypted on the fly through
+/// [`Read`], so only the 256-byte keystream is kept in memory.
+///
+/// Recommended if you want to dump large ncm files without buffering the
+/// whole music. If the underlying reader implements [`Seek`], any range of
+/// the music data can be decrypted without touching the rest, see
+/// [`decrypt_range()`](NCMReader::decrypt_range()).
+/// # Example
+/// ```no_run
+/// use std::fs::File;
+///
+/// let mut reader = ncm_parser::NCMReader::new(File::open("xxx.ncm").unwrap()).unwrap();
+/// let mut music = File::create("xxx.flac").unwrap();
+/// std::io::copy(&mut reader, &mut music).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct NCMReader<R> {
+    inner: R,
+    key: NCMKey,
+    pos: u64,
+    header: NCMHeader,
+    /// The decrypted metadata along with its kind, kept after the first
+    /// call to [`decrypted_metadata()`](NCMReader::decrypted_metadata()).
+    metadata: Option<(MetadataKind, Vec<u8>)>,
+}
+
+impl<R: Read> NCMReader<R> {
+    /// Parse everything in front of the music data and decrypt the RC4 key.
+    /// The reader is left at the beginning of the music data.
+    pub fn new(mut reader: R) -> Result<Self, ParseError> {
+        let header = parse_header(&mut reader)?;
+        Ok(Self {
+            inner: reader,
+            key: NCMKey::new(&header.rc4_key)?,
+            pos: 0,
+            header,
+            metadata: None,
+        })
+    }
+}
+
+impl<R: Read + Seek> NCMReader<R> {
+    /// Decrypt `len` bytes of the music data starting from `offset`.
+    /// The returned data is shorter than `len` if it reaches the end of the
+    /// music data. The reader is left at the end of the returned range.
+    /// # Example
+    /// ```no_run
+    /// use std::fs::File;
+    ///
+    /// let mut reader = ncm_parser::NCMReader::new(File::open("xxx.ncm").unwrap()).unwrap();
+    /// // Get the first 4 bytes of the music, such as "fLaC" or "ID3\x03".
+    /// let music_header = reader.decrypt_range(0, 4).unwrap();
+    /// ```
+    pub fn decrypt_range(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        self.seek(SeekFrom::Start(offset))?;
+        // Don't allocate `len` bytes in advance, which may be far beyond the
+        // end of the music data.
+        let mut music = Vec::new();
+        self.by_ref().take(len as u64).read_to_end(&mut music)?;
+        Ok(music)
+    }
+}
+
+impl<R> NCMReader<R> {
+    /// Get everything parsed in front of the music data.
+    pub fn get_header(&self) -> &NCMHeader {
+        &self.header
+    }
+
+    /// Get cover image. Usually in PNG or JPEG format.
+    pub fn get_image(&self) -> &Vec<u8> {
+        self.header.get_image()
+    }
+
+    /// Detect the format of the cover image from its first bytes. Returns
+    /// `None` if the file has no cover image or the format is unknown.
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        self.header.image_format()
+    }
+
+    /// Get decrypted metadata.
+    pub fn get_metadata(&self) -> Result<Vec<u8>, ParseError> {
+        self.header.get_metadata()
+    }
+
+    /// Get the kind of the metadata.
+    pub fn get_metadata_kind(&self) -> Result<MetadataKind, ParseError> {
+        self.header.get_metadata_kind()
+    }
+
+    /// Directly get metadata, which is not decrypted.
+    pub fn get_metadata_unchecked(&self) -> &Vec<u8> {
+        self.header.get_metadata_unchecked()
+    }
+
+    #[cfg(feature = "serde")]
+    /// Parse the JSON format metadata into struct.
+    /// For radio or podcast programs, the song in `mainMusic` is returned.
+    pub fn get_parsed_metadata(&self) -> Result<NCMMetadata, ParseError> {
+        self.header.get_parsed_metadata()
+    }
+
+    #[cfg(feature = "serde")]
+    /// Parse the JSON format metadata of radio or podcast programs into struct.
+    pub fn get_parsed_dj_metadata(&self) -> Result<DJMetadata, ParseError> {
+        self.header.get_parsed_dj_metadata()
+    }
+
+    /// Decrypt the metadata only once for the methods of
+    /// [`EncryptedTrack`](crate::EncryptedTrack), which might be called
+    /// several times.
+    pub(crate) fn decrypted_metadata(
+        &mut self,
+    ) -> Result<(MetadataKind, &[u8]), ParseError> {
+        if self.metadata.is_none() {
+            let metadata = self.header.get_metadata_unchecked();
+            self.metadata = Some(crate::decrypt_metadata(metadata)?);
+        }
+        let (kind, metadata) = self.metadata.as_ref().unwrap();
+        Ok((*kind, metadata))
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// Reading from the underlying reader directly would mess up the
+    /// decryption of the rest music data.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `NCMReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for NCMReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.key.apply(&mut buf[..len], self.pos);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+/// The positions are counted from the beginning of the music data.
+/// Seeking to a position before the music data is an error.
+impl<R: Read + Seek> Seek for NCMReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        // The position of the music data in the underlying reader.
+        let music_start = self.inner.stream_position()? - self.pos;
+        let pos = match pos {
+            SeekFrom::Start(offset) => SeekFrom::Start(music_start + offset),
+            pos => pos,
+        };
+
+        let new_pos = self.inner.seek(pos)?;
+        if new_pos < music_start {
+            self.inner.seek(SeekFrom::Start(music_start + self.pos))?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a position before the music data",
+            ));
+        }
+        self.pos = new_pos - music_start;
+        Ok(self.pos)
+    }
+}
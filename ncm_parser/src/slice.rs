@@ -0,0 +1,196 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::ParseError::{self, *};
+use crate::{
+    decrypt_metadata, AudioFormat, ImageFormat, MetadataKind, NCMKey, Segment,
+};
+#[cfg(feature = "serde")]
+use crate::{parse_json, parse_song, DJMetadata, NCMMetadata};
+
+/// Parse the ncm file stored in a slice without copying anything.
+/// Recommended if you have a lot of ncm files in memory, e.g. memory-mapped.
+/// # Example
+/// ```no_run
+/// let data = std::fs::read("xxx.ncm").unwrap();
+/// let ncm_file = ncm_parser::from_slice(&data).unwrap();
+///
+/// let mut music = vec![0; ncm_file.get_music_unchecked().len()];
+/// ncm_file.decrypt_music_into(&mut music).unwrap();
+/// ```
+pub fn from_slice(data: &[u8]) -> Result<NCMFileRef<'_>, ParseError> {
+    let mut rest = data;
+    let mut offset = 0;
+    let ncm_header = read_exact_slice(&mut rest, 10, Segment::Header, &mut offset)?;
+    if ncm_header[0..8] != *b"CTENFDAM" {
+        return Err(InvalidHeader);
+    }
+    let rc4_key = read_segment_slice(&mut rest, Segment::Key, &mut offset)?;
+    let metadata = read_segment_slice(&mut rest, Segment::Metadata, &mut offset)?;
+    let gap = read_exact_slice(&mut rest, 9, Segment::Gap, &mut offset)?;
+    let crc32 = u32::from_le_bytes(gap[0..4].try_into().unwrap());
+    let image_frame_len = u32::from_le_bytes(gap[5..9].try_into().unwrap());
+    let image = read_segment_slice(&mut rest, Segment::Image, &mut offset)?;
+    let padding = image_frame_len.saturating_sub(image.len() as u32);
+    read_exact_slice(&mut rest, padding as usize, Segment::Image, &mut offset)?;
+    Ok(NCMFileRef {
+        rc4_key,
+        metadata,
+        crc32,
+        image_frame_len,
+        image,
+        music: rest,
+    })
+}
+
+/// Split `len` bytes from the front of `data`. `offset` is the position of
+/// `data` in the ncm file, which moves forward after reading.
+fn read_exact_slice<'a>(
+    data: &mut &'a [u8],
+    len: usize,
+    segment: Segment,
+    offset: &mut u64,
+) -> Result<&'a [u8], ParseError> {
+    if data.len() < len {
+        return Err(EndOfFile {
+            segment,
+            offset: *offset,
+            expected: len as u64,
+            available: data.len() as u64,
+        });
+    }
+    let (chunk, rest) = data.split_at(len);
+    *data = rest;
+    *offset += len as u64;
+    Ok(chunk)
+}
+
+/// The slice version of [`read_segment_iter()`](crate::read_segment_iter()),
+/// but the segment data is still XORed with the salt.
+fn read_segment_slice<'a>(
+    data: &mut &'a [u8],
+    segment: Segment,
+    offset: &mut u64,
+) -> Result<&'a [u8], ParseError> {
+    let seg_len = read_exact_slice(data, 4, segment, offset)?;
+    let seg_len = u32::from_le_bytes(seg_len.try_into().unwrap());
+    read_exact_slice(data, seg_len as usize, segment, offset)
+}
+
+/// A struct borrows all the data from the ncm file stored in a slice.
+/// Nothing is decrypted until asked, and the music is decrypted into the
+/// buffer provided by the caller.
+#[derive(Debug, Clone, Copy)]
+pub struct NCMFileRef<'a> {
+    rc4_key: &'a [u8],
+    metadata: &'a [u8],
+    crc32: u32,
+    image_frame_len: u32,
+    image: &'a [u8],
+    music: &'a [u8],
+}
+
+impl<'a> NCMFileRef<'a> {
+    /// Decrypt the music into `dst`, returns the number of bytes decrypted,
+    /// which is the smaller one of the music length and `dst.len()`.
+    pub fn decrypt_music_into(&self, dst: &mut [u8]) -> Result<usize, ParseError> {
+        let key = self.get_key()?;
+        let len = dst.len().min(self.music.len());
+        dst[..len].copy_from_slice(&self.music[..len]);
+        key.apply(&mut dst[..len], 0);
+        Ok(len)
+    }
+
+    /// Detect the format of the music from its first bytes, which is more
+    /// reliable than the `format` in the metadata. Returns `None` if unknown.
+    pub fn audio_format(&self) -> Result<Option<AudioFormat>, ParseError> {
+        let mut header = [0; AudioFormat::SNIFF_LEN];
+        let len = self.decrypt_music_into(&mut header)?;
+        // Decrypt the bytes after the ID3v2 tag as well, if any.
+        let sniff_len = AudioFormat::sniff_len(&header[..len]);
+        if sniff_len <= len {
+            return Ok(AudioFormat::from_header(&header[..len]));
+        }
+        let mut header = vec![0; sniff_len.min(self.music.len())];
+        let len = self.decrypt_music_into(&mut header)?;
+        Ok(AudioFormat::from_header(&header[..len]))
+    }
+
+    /// Get cover image. Usually in PNG or JPEG format.
+    pub fn get_image(&self) -> &'a [u8] {
+        self.image
+    }
+
+    /// Detect the format of the cover image from its first bytes. Returns
+    /// `None` if the file has no cover image or the format is unknown.
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        ImageFormat::from_header(self.image)
+    }
+
+    /// Get decrypted metadata.
+    pub fn get_metadata(&self) -> Result<Vec<u8>, ParseError> {
+        self.decrypt_metadata().map(|(_, metadata)| metadata)
+    }
+
+    /// Get the kind of the metadata.
+    pub fn get_metadata_kind(&self) -> Result<MetadataKind, ParseError> {
+        self.decrypt_metadata().map(|(kind, _)| kind)
+    }
+
+    fn decrypt_metadata(&self) -> Result<(MetadataKind, Vec<u8>), ParseError> {
+        let metadata: Vec<u8> = self.metadata.iter().map(|x| x ^ 0x63).collect();
+        decrypt_metadata(&metadata)
+    }
+
+    #[cfg(feature = "serde")]
+    /// Parse the JSON format metadata into struct.
+    /// For radio or podcast programs, the song in `mainMusic` is returned.
+    pub fn get_parsed_metadata(&self) -> Result<NCMMetadata, ParseError> {
+        let (kind, metadata) = self.decrypt_metadata()?;
+        parse_song(kind, &metadata)
+    }
+
+    #[cfg(feature = "serde")]
+    /// Parse the JSON format metadata of radio or podcast programs into struct.
+    pub fn get_parsed_dj_metadata(&self) -> Result<DJMetadata, ParseError> {
+        parse_json(&self.get_metadata()?)
+    }
+
+    /// Directly get the RC4 key segment as it is stored in the ncm file,
+    /// which is still XORed with `0x64`.
+    pub fn get_rc4_key_raw(&self) -> &'a [u8] {
+        self.rc4_key
+    }
+
+    /// Directly get the metadata segment as it is stored in the ncm file,
+    /// which is still XORed with `0x63`, unlike the `get_metadata_unchecked()`
+    /// of [`NCMFile`](crate::NCMFile) and [`NCMHeader`](crate::NCMHeader).
+    pub fn get_metadata_raw(&self) -> &'a [u8] {
+        self.metadata
+    }
+
+    /// Directly get the encrypted music.
+    pub fn get_music_unchecked(&self) -> &'a [u8] {
+        self.music
+    }
+
+    /// The CRC32 stored in front of the image segment. It is not verified,
+    /// since what the clients calculate it from is unknown.
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    /// The space reserved for the cover image, which may be larger than
+    /// the length of the image. The music data starts after the reserved
+    /// space. Old files may store a smaller value, which is ignored then.
+    pub fn image_frame_len(&self) -> u32 {
+        self.image_frame_len
+    }
+
+    /// Decrypt the RC4 key, which can be used to decrypt the music with
+    /// [`decrypt_music_in_place()`](crate::decrypt_music_in_place()).
+    pub fn get_key(&self) -> Result<NCMKey, ParseError> {
+        let rc4_key: Vec<u8> = self.rc4_key.iter().map(|x| x ^ 0x64).collect();
+        NCMKey::new(&rc4_key)
+    }
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "tokio")]
+use ncm_parser::{AsyncNCMReader, ImageFormat};
+use tokio::io::AsyncReadExt;
+
+mod common;
+
+#[tokio::test]
+async fn async_round_trip() {
+    let (music, metadata, image) =
+        (common::music(), common::METADATA, common::IMAGE);
+    let ncm = common::builder().image_frame_len(100).build();
+
+    let mut ncm_file = ncm_parser::from_async_reader(&ncm[..]).await.unwrap();
+    assert_eq!(ncm_file.get_metadata().unwrap(), &metadata);
+    assert_eq!(ncm_file.get_image().unwrap(), &image);
+    assert_eq!(ncm_file.get_music().unwrap(), &music);
+
+    let mut reader = AsyncNCMReader::new(&ncm[..]).await.unwrap();
+    assert_eq!(reader.get_metadata().unwrap(), metadata);
+    assert_eq!(reader.get_image(), &image);
+    assert_eq!(reader.image_format(), ImageFormat::from_header(image));
+
+    // Decrypt the music from the underlying reader with the key.
+    let offset = reader.get_header().music_offset() as usize;
+    let mut buf = ncm[offset + 500..offset + 600].to_vec();
+    ncm_parser::decrypt_music_in_place(&mut buf, reader.get_key(), 500);
+    assert_eq!(buf, music[500..600]);
+    let mut music_from_reader = Vec::new();
+    reader.read_to_end(&mut music_from_reader).await.unwrap();
+    assert_eq!(music_from_reader, music);
+}
@@ -0,0 +1,109 @@
+use common::build_ncm;
+use ncm_parser::NCMBuilder;
+
+mod common;
+
+/// An ncm file built by a separate implementation, with the RC4 key
+/// `123456`, the metadata `{"musicName":"test","format":"flac"}`, an 8-byte
+/// PNG signature as the image in a 16-byte frame, the CRC32 `0x12345678`
+/// and the music from [`known_music()`].
+const KNOWN_NCM: [u8; 197] = [
+    0x43, 0x54, 0x45, 0x4e, 0x46, 0x44, 0x41, 0x4d, 0x00, 0x00, 0x20, 0x00, 0x00,
+    0x00, 0x2c, 0xce, 0xd5, 0xeb, 0x69, 0xea, 0xfb, 0x14, 0x55, 0x0d, 0x45, 0xbf,
+    0x61, 0xdd, 0x17, 0x1d, 0x0e, 0xdf, 0x00, 0xe7, 0xfa, 0x15, 0x3e, 0x89, 0xd6,
+    0xc8, 0xe3, 0xe9, 0x71, 0x64, 0x62, 0xd1, 0x56, 0x00, 0x00, 0x00, 0x52, 0x55,
+    0x50, 0x43, 0x08, 0x06, 0x1a, 0x4b, 0x27, 0x0c, 0x0d, 0x44, 0x17, 0x43, 0x0e,
+    0x0c, 0x07, 0x0a, 0x05, 0x1a, 0x4a, 0x59, 0x2e, 0x01, 0x48, 0x12, 0x4c, 0x25,
+    0x29, 0x12, 0x0a, 0x57, 0x32, 0x31, 0x01, 0x24, 0x24, 0x25, 0x51, 0x5b, 0x04,
+    0x0c, 0x06, 0x14, 0x17, 0x52, 0x50, 0x2a, 0x0f, 0x48, 0x1a, 0x39, 0x15, 0x0c,
+    0x08, 0x19, 0x0b, 0x12, 0x07, 0x30, 0x57, 0x53, 0x55, 0x39, 0x5b, 0x2b, 0x3b,
+    0x35, 0x2e, 0x36, 0x5a, 0x1a, 0x16, 0x26, 0x29, 0x14, 0x19, 0x52, 0x35, 0x50,
+    0x0e, 0x3b, 0x54, 0x2b, 0x2c, 0x07, 0x78, 0x56, 0x34, 0x12, 0x00, 0x10, 0x00,
+    0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a,
+    0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x12, 0x06, 0xb0, 0x27,
+    0xaf, 0xce, 0x4b, 0x28, 0x17, 0xd2, 0x25, 0x9e, 0x24, 0x94, 0xf9, 0x87, 0x4a,
+    0x86, 0x0e, 0x99, 0xcd, 0x82, 0x5a, 0x1f, 0xb4, 0x8c, 0x2f, 0xd9, 0x80, 0x78,
+    0x6e, 0x87,
+];
+
+/// 32 bytes of music starting with the FLAC magic.
+fn known_music() -> Vec<u8> {
+    [b"fLaC\0\0\0\x22".as_slice(), &(0..24).collect::<Vec<u8>>()].concat()
+}
+
+#[test]
+fn round_trip() {
+    let (ncm, music, metadata, image) = build_ncm();
+
+    let mut ncm_from_iter = ncm_parser::from_iter(ncm.clone().into_iter()).unwrap();
+    assert_eq!(ncm_from_iter.get_metadata().unwrap(), &metadata);
+    assert_eq!(ncm_from_iter.get_image().unwrap(), &image);
+    assert_eq!(ncm_from_iter.get_music().unwrap(), &music);
+
+    let ncm_from_slice = ncm_parser::from_slice(&ncm).unwrap();
+    assert_eq!(ncm_from_slice.get_metadata().unwrap(), metadata);
+    assert_eq!(ncm_from_slice.get_image(), image);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn reader_round_trip() {
+    use std::io::{Cursor, Read};
+
+    use ncm_parser::NCMReader;
+
+    let (ncm, music, metadata, image) = build_ncm();
+
+    let mut ncm_from_reader = ncm_parser::from_reader(Cursor::new(&ncm)).unwrap();
+    assert_eq!(ncm_from_reader.get_metadata().unwrap(), &metadata);
+    assert_eq!(ncm_from_reader.get_image().unwrap(), &image);
+    assert_eq!(ncm_from_reader.get_music().unwrap(), &music);
+
+    let mut reader = NCMReader::new(Cursor::new(&ncm)).unwrap();
+    assert_eq!(reader.get_metadata().unwrap(), metadata);
+    assert_eq!(reader.get_image(), &image);
+    let mut music_from_reader = Vec::new();
+    reader.read_to_end(&mut music_from_reader).unwrap();
+    assert_eq!(music_from_reader, music);
+}
+
+#[test]
+fn known_answer() {
+    let metadata = br#"{"musicName":"test","format":"flac"}"#;
+    let image = b"\x89PNG\r\n\x1a\n";
+    let music = known_music();
+    let ncm = NCMBuilder::new(music.clone(), b"123456".to_vec())
+        .raw_metadata(metadata.to_vec())
+        .image(image.to_vec())
+        .image_frame_len(16)
+        .crc32(0x12345678)
+        .build();
+    assert_eq!(ncm, KNOWN_NCM);
+
+    let ncm_file = ncm_parser::from_slice(&KNOWN_NCM).unwrap();
+    assert_eq!(ncm_file.get_metadata().unwrap(), metadata);
+    assert_eq!(ncm_file.get_image(), image);
+    assert_eq!(ncm_file.image_frame_len(), 16);
+    assert_eq!(ncm_file.crc32(), 0x12345678);
+    let mut buf = [0; 16];
+    assert_eq!(ncm_file.decrypt_music_into(&mut buf).unwrap(), 16);
+    assert_eq!(buf, music[..16]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn known_answer_header() {
+    let header = ncm_parser::parse_header(KNOWN_NCM.as_slice()).unwrap();
+    assert_eq!(header.key_offset(), 14);
+    assert_eq!(header.key_len(), 32);
+    assert_eq!(header.metadata_offset(), 50);
+    assert_eq!(header.metadata_len(), 86);
+    assert_eq!(header.crc32(), 0x12345678);
+    assert_eq!(header.image_offset(), 149);
+    assert_eq!(header.image_len(), 8);
+    assert_eq!(header.image_frame_len(), 16);
+    assert_eq!(header.music_offset(), 165);
+    assert!(header
+        .get_metadata_unchecked()
+        .starts_with(b"163 key(Don't modify):"));
+}
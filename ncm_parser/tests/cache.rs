@@ -0,0 +1,53 @@
+mod common;
+
+#[test]
+fn decrypt_cache() {
+    let music = common::music();
+    let mut cache = music.clone();
+    ncm_parser::decrypt_cache_in_place(&mut cache);
+    assert!(cache.iter().zip(&music).all(|(x, y)| x ^ y == 0xa3));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn cache_reader() {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use ncm_parser::CacheReader;
+
+    let music = common::music();
+    let mut cache = music.clone();
+    ncm_parser::decrypt_cache_in_place(&mut cache);
+
+    let mut reader = CacheReader::new(Cursor::new(&cache));
+    let mut decrypted = Vec::new();
+    reader.read_to_end(&mut decrypted).unwrap();
+    assert_eq!(decrypted, music);
+
+    reader.seek(SeekFrom::Start(500)).unwrap();
+    let mut buf = [0; 10];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, music[500..510]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn parse_cache_info() {
+    use ncm_parser::CacheInfo;
+
+    let info = CacheInfo::new(
+        br#"{"musicId":"1234","bitrate":320000,"filesize":"4096","md5":"abc","zone":["0 4095"]}"#,
+    )
+    .unwrap();
+    assert_eq!(info.music_id, "1234");
+    assert_eq!(info.bitrate, 320000);
+    assert_eq!(info.size, 4096);
+    assert_eq!(info.md5.as_deref(), Some("abc"));
+    assert_eq!(info.extra["zone"][0], "0 4095");
+
+    let info = CacheInfo::from_file_name("1234-128-0123abcd.uc!").unwrap();
+    assert_eq!(info.music_id, "1234");
+    assert_eq!(info.bitrate, 128);
+    assert_eq!(info.md5.as_deref(), Some("0123abcd"));
+    assert!(CacheInfo::from_file_name("song.uc").is_none());
+}
@@ -0,0 +1,34 @@
+//! The fixtures shared by the tests.
+#![allow(dead_code)]
+
+use ncm_parser::NCMBuilder;
+
+/// The RC4 key the ncm files are built with.
+pub const KEY: &[u8] = b"1234567890abcdef";
+/// The `music:` metadata without the prefix.
+pub const METADATA: &[u8] = br#"{"musicName":"test","format":"flac"}"#;
+/// Only the magic bytes are real.
+pub const IMAGE: &[u8] = b"\xff\xd8\xff\xe0 not a real jpeg";
+
+/// 100,000 bytes of music, longer than the keystream and the read buffers.
+pub fn music() -> Vec<u8> {
+    (0..100_000u32).map(|x| (x * 7 % 251) as u8).collect()
+}
+
+/// A builder with [`music()`], [`KEY`], [`METADATA`] and [`IMAGE`].
+pub fn builder() -> NCMBuilder {
+    NCMBuilder::new(music(), KEY.to_vec())
+        .raw_metadata(METADATA.to_vec())
+        .image(IMAGE.to_vec())
+}
+
+/// Build an ncm file with [`builder()`], returns the file, its music,
+/// metadata and image.
+pub fn build_ncm() -> (Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>) {
+    (
+        builder().build(),
+        music(),
+        METADATA.to_vec(),
+        IMAGE.to_vec(),
+    )
+}
@@ -0,0 +1,41 @@
+use common::build_ncm;
+
+mod common;
+
+#[test]
+fn decrypt_into_buffers() {
+    let (ncm, music, _, _) = build_ncm();
+
+    let mut ncm_file = ncm_parser::from_iter(ncm.clone().into_iter()).unwrap();
+    let mut buf = vec![0; music.len()];
+    assert_eq!(ncm_file.decrypt_music_into(&mut buf).unwrap(), music.len());
+    assert_eq!(buf, music);
+    // Still works after the music is decrypted by `get_music()`.
+    ncm_file.get_music().unwrap();
+    let mut buf = [0; 100];
+    assert_eq!(ncm_file.decrypt_music_into(&mut buf).unwrap(), 100);
+    assert_eq!(buf, music[..100]);
+
+    let key = ncm_file.get_key().unwrap();
+    let mut encrypted = ncm[ncm.len() - music.len()..].to_vec();
+    for (i, chunk) in encrypted.chunks_mut(1000).enumerate() {
+        ncm_parser::decrypt_music_in_place(chunk, &key, i as u64 * 1000);
+    }
+    assert_eq!(encrypted, music);
+}
+
+#[test]
+fn decrypt_into_after_failed_get_music() {
+    let (mut ncm, music, _, _) = build_ncm();
+    // Corrupt the first block of the AES-128 encrypted RC4 key.
+    ncm[14] ^= 0xff;
+
+    let mut ncm_file = ncm_parser::from_iter(ncm.into_iter()).unwrap();
+    let encrypted = ncm_file.get_music_unchecked().clone();
+    assert!(ncm_file.get_music().is_err());
+    assert_eq!(ncm_file.get_music_unchecked(), &encrypted);
+    // Must not return the encrypted music as if it were decrypted.
+    let mut buf = vec![0; music.len()];
+    assert!(ncm_file.decrypt_music_into(&mut buf).is_err());
+    assert!(ncm_file.get_music().is_err());
+}
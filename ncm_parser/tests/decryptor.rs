@@ -0,0 +1,149 @@
+#![cfg(feature = "std")]
+use std::io::{Cursor, Read};
+
+use ncm_parser::{
+    decrypt_cache_in_place, Decryptor, DecryptorKind, EncryptedTrack, MetadataKind,
+    ParseError, Registry,
+};
+
+mod common;
+
+#[test]
+fn registry_find() {
+    let registry = Registry::new();
+    let name = |magic: &[u8], ext| registry.find(magic, ext).map(|d| d.name());
+    assert_eq!(name(b"CTENFDAM\x01\x70", "mp3"), Some("ncm"));
+    assert_eq!(name(b"yeelion-kuwo-tme", "ncm"), Some("kwm"));
+    assert_eq!(name(&[0; 16], "QMCFLAC"), Some("qmc"));
+    assert_eq!(name(&[0; 16], "uc!"), Some("cache"));
+    assert_eq!(name(&[0; 4], "kgma"), Some("kgm"));
+    assert_eq!(name(&[0; 16], ""), None);
+    assert!(Registry::empty().find(b"CTENFDAM", "ncm").is_none());
+
+    // The format can be told by its kind as well.
+    let kind = |magic: &[u8], ext| registry.find(magic, ext).map(|d| d.kind());
+    assert_eq!(kind(b"CTENFDAM", ""), Some(DecryptorKind::Ncm));
+    assert_eq!(kind(&[0; 16], "uc"), Some(DecryptorKind::Cache));
+}
+
+#[test]
+fn open_ncm() {
+    let (ncm, music, metadata, image) = common::build_ncm();
+
+    let registry = Registry::new();
+    let decryptor = registry.find(&ncm[..16], "ncm").unwrap();
+    let mut track = decryptor.open(Box::new(Cursor::new(&ncm)), "ncm").unwrap();
+    assert_eq!(track.cover(), Some(image.as_slice()));
+    let raw_metadata = track.raw_metadata().unwrap().to_vec();
+    assert!(raw_metadata.starts_with(b"163 key(Don't modify):"));
+    assert_eq!(track.metadata().unwrap(), Some(metadata.clone()));
+    // The metadata decrypted before is returned again.
+    assert_eq!(track.metadata().unwrap(), Some(metadata.clone()));
+    assert_eq!(track.metadata_kind().unwrap(), MetadataKind::Music);
+    let mut decrypted = Vec::new();
+    track.music().unwrap().read_to_end(&mut decrypted).unwrap();
+    assert_eq!(decrypted, music);
+
+    let mut ncm_file = ncm_parser::from_reader(Cursor::new(&ncm)).unwrap();
+    let track: &mut dyn EncryptedTrack = &mut ncm_file;
+    assert_eq!(track.raw_metadata(), Some(raw_metadata.as_slice()));
+    assert_eq!(track.metadata().unwrap(), Some(metadata));
+    // Still kept after the metadata is decrypted.
+    assert_eq!(track.raw_metadata(), Some(raw_metadata.as_slice()));
+    let mut decrypted = Vec::new();
+    track.music().unwrap().read_to_end(&mut decrypted).unwrap();
+    assert_eq!(decrypted, music);
+}
+
+/// A format from outside the crate, whose music is reversed bytewise.
+struct Reversed;
+
+struct ReversedTrack(Vec<u8>);
+
+impl EncryptedTrack for ReversedTrack {
+    fn music(&mut self) -> Result<Box<dyn Read + '_>, ParseError> {
+        self.0.reverse();
+        Ok(Box::new(self.0.as_slice()))
+    }
+}
+
+impl Decryptor for Reversed {
+    fn name(&self) -> &'static str {
+        "reversed"
+    }
+
+    fn probe_extension(&self, extension: &str) -> bool {
+        extension == "uc"
+    }
+
+    fn open<'a>(
+        &self,
+        mut reader: Box<dyn Read + 'a>,
+        _extension: &str,
+    ) -> Result<Box<dyn EncryptedTrack + 'a>, ParseError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        Ok(Box::new(ReversedTrack(data)))
+    }
+}
+
+#[test]
+fn custom_decryptor() {
+    let mut registry = Registry::empty();
+    registry.register(Reversed);
+    registry.register(ncm_parser::CacheDecryptor);
+    let decryptor = registry.find(b"whatever", "uc").unwrap();
+    assert_eq!(decryptor.name(), "reversed");
+    // Not taken for any of the built-in formats.
+    assert_eq!(decryptor.kind(), DecryptorKind::Other);
+    let mut track = decryptor.open(Box::new(&b"321"[..]), "uc").unwrap();
+    assert_eq!(track.metadata().unwrap(), None);
+    assert_eq!(track.cover(), None);
+    let mut music = Vec::new();
+    track.music().unwrap().read_to_end(&mut music).unwrap();
+    assert_eq!(music, b"123");
+
+    let mut cache = b"fLaC".to_vec();
+    decrypt_cache_in_place(&mut cache);
+    let decryptor = registry.find(b"", "uc!").unwrap();
+    let mut track = decryptor.open(Box::new(cache.as_slice()), "uc!").unwrap();
+    let mut music = Vec::new();
+    track.music().unwrap().read_to_end(&mut music).unwrap();
+    assert_eq!(music, b"fLaC");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn parsed_metadata_of_any_track() {
+    use ncm_parser::{EncryptedTrackExt, MetadataKind, NCMBuilder};
+
+    let song = r#"{"musicId":"1","musicName":"m","artist":[],"albumId":3,"album":"c","albumPicDocId":4,"albumPic":"","bitrate":320000,"duration":1000,"alias":[],"transNames":[],"format":"mp3"}"#;
+    let dj = format!(r#"{{"programName":"p","mainMusic":{song}}}"#);
+    let registry = Registry::new();
+    for (ncm, kind) in [
+        (
+            NCMBuilder::new(Vec::new(), common::KEY.to_vec())
+                .raw_metadata(song.into())
+                .build(),
+            MetadataKind::Music,
+        ),
+        (
+            NCMBuilder::new(Vec::new(), common::KEY.to_vec())
+                .raw_dj_metadata(dj.into())
+                .build(),
+            MetadataKind::Dj,
+        ),
+    ] {
+        let mut track = registry
+            .find(&ncm[..16], "ncm")
+            .unwrap()
+            .open(Box::new(Cursor::new(&ncm)), "ncm")
+            .unwrap();
+        assert_eq!(track.metadata_kind().unwrap(), kind);
+        assert_eq!(track.parsed_metadata().unwrap().unwrap().music_name, "m");
+    }
+
+    let mut track = Reversed.open(Box::new(&b"321"[..]), "uc").unwrap();
+    assert_eq!(track.metadata_kind().unwrap(), MetadataKind::None);
+    assert!(track.parsed_metadata().unwrap().is_none());
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "std")]
+use std::io::{self, Cursor, Read};
+
+use common::build_ncm;
+
+mod common;
+
+#[test]
+fn truncated_file() {
+    use ncm_parser::{ParseError, Segment};
+
+    let (ncm, _, _, image) = build_ncm();
+    let header = ncm_parser::parse_header(Cursor::new(&ncm)).unwrap();
+    let truncated = &ncm[..header.image_offset() as usize + 10];
+
+    let err = ncm_parser::from_reader(Cursor::new(truncated)).unwrap_err();
+    assert!(matches!(
+        err,
+        ParseError::EndOfFile {
+            segment: Segment::Image,
+            offset,
+            expected,
+            available: 10,
+        } if offset == header.image_offset() && expected == image.len() as u64
+    ));
+
+    let err = ncm_parser::from_iter(truncated.iter().copied()).unwrap_err();
+    assert!(matches!(
+        err,
+        ParseError::EndOfFile {
+            segment: Segment::Image,
+            available: 10,
+            ..
+        }
+    ));
+
+    let err = ncm_parser::from_iter(ncm[..5].iter().copied()).unwrap_err();
+    assert!(matches!(
+        err,
+        ParseError::EndOfFile {
+            segment: Segment::Header,
+            offset: 0,
+            expected: 10,
+            available: 5,
+        }
+    ));
+}
+
+/// Returns an error after reading `fail_at` bytes.
+struct BrokenReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    fail_at: usize,
+}
+
+impl Read for BrokenReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.fail_at {
+            return Err(io::Error::other("broken reader"));
+        }
+        // Read a few bytes at a time, so that the segment is partially read.
+        let end = self.data.len().min(self.fail_at).min(self.pos + 4);
+        let len = buf.len().min(end - self.pos);
+        buf[..len].copy_from_slice(&self.data[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[test]
+fn reader_error() {
+    use ncm_parser::{ParseError, Segment};
+
+    let (ncm, _, _, _) = build_ncm();
+    let header = ncm_parser::parse_header(Cursor::new(&ncm)).unwrap();
+    let reader = BrokenReader {
+        data: &ncm,
+        pos: 0,
+        fail_at: header.image_offset() as usize + 10,
+    };
+
+    let err = ncm_parser::from_reader(reader).unwrap_err();
+    let ParseError::Io {
+        segment: Segment::Image,
+        offset,
+        source,
+    } = err
+    else {
+        panic!("unexpected error: {err:?}");
+    };
+    assert_eq!(offset, header.image_offset() + 10);
+    assert_eq!(source.kind(), io::ErrorKind::Other);
+    assert_eq!(source.to_string(), "broken reader");
+}
@@ -0,0 +1,119 @@
+use ncm_parser::{AudioFormat, ImageFormat, NCMBuilder};
+
+mod common;
+
+#[test]
+fn sniff_audio() {
+    let cases: [(&[u8], Option<AudioFormat>); 13] = [
+        (b"\xff\xfb\x90\x64\0\0\0\0", Some(AudioFormat::Mp3)),
+        // The ID3v2 tag is skipped, whose size is 0x81 in syncsafe bytes.
+        (
+            &[
+                b"ID3\x04\0\0\0\0\x01\x01",
+                &[0; 0x81][..],
+                b"\xff\xfb\x90\x64",
+            ]
+            .concat(),
+            Some(AudioFormat::Mp3),
+        ),
+        (b"ID3\x04\0\0\0\0\0\x02\0\0fLaC", Some(AudioFormat::Flac)),
+        // A footer follows the tag.
+        (
+            &[b"ID3\x04\0\x10\0\0\0\0", &[0; 10][..], b"fLaC"].concat(),
+            Some(AudioFormat::Flac),
+        ),
+        (b"ID3\x04\0\0\0\0\0\x02\0\0", None),
+        (b"ID3\x04\0\0\0\0", None),
+        (b"\xff\xf1\x50\x80\0\0\0\0", None),
+        (b"fLaC\0\0\0\x22", Some(AudioFormat::Flac)),
+        (b"OggS\0\x02\0\0", Some(AudioFormat::Ogg)),
+        (b"RIFF\x24\0\0\0", Some(AudioFormat::Wav)),
+        (b"\0\0\0\x20ftypM4A ", Some(AudioFormat::M4a)),
+        (b"fLa", None),
+        (b"", None),
+    ];
+    for (header, format) in cases {
+        assert_eq!(AudioFormat::from_header(header), format);
+    }
+}
+
+#[test]
+fn ncm_audio_format() {
+    let mut music = b"fLaC\0\0\0\x22".to_vec();
+    music.resize(1000, 0);
+    // The metadata says mp3 but the music is FLAC.
+    let ncm = NCMBuilder::new(music, common::KEY.to_vec())
+        .raw_metadata(br#"{"format":"mp3"}"#.to_vec())
+        .build();
+
+    let mut ncm_file = ncm_parser::from_iter(ncm.clone().into_iter()).unwrap();
+    assert_eq!(ncm_file.audio_format().unwrap(), Some(AudioFormat::Flac));
+    ncm_file.get_music().unwrap();
+    assert_eq!(ncm_file.audio_format().unwrap(), Some(AudioFormat::Flac));
+
+    let ncm_file = ncm_parser::from_slice(&ncm).unwrap();
+    assert_eq!(ncm_file.audio_format().unwrap(), Some(AudioFormat::Flac));
+
+    let ncm = NCMBuilder::new(Vec::new(), common::KEY.to_vec()).build();
+    let ncm_file = ncm_parser::from_slice(&ncm).unwrap();
+    assert_eq!(ncm_file.audio_format().unwrap(), None);
+
+    // The ID3v2 tag in front is skipped.
+    let mut music = b"ID3\x04\0\0\0\0\x01\x01".to_vec();
+    music.resize(10 + 0x81, 0);
+    music.extend_from_slice(b"fLaC\0\0\0\x22");
+    let ncm = NCMBuilder::new(music, common::KEY.to_vec()).build();
+    let ncm_file = ncm_parser::from_iter(ncm.clone().into_iter()).unwrap();
+    assert_eq!(ncm_file.audio_format().unwrap(), Some(AudioFormat::Flac));
+    let ncm_file = ncm_parser::from_slice(&ncm).unwrap();
+    assert_eq!(ncm_file.audio_format().unwrap(), Some(AudioFormat::Flac));
+}
+
+#[test]
+fn sniff_image() {
+    let cases: [(&[u8], Option<ImageFormat>); 7] = [
+        (b"\xff\xd8\xff\xe0\0\x10JFIF\0\x01", Some(ImageFormat::Jpeg)),
+        (b"\x89PNG\r\n\x1a\n\0\0\0\x0d", Some(ImageFormat::Png)),
+        (b"RIFF\x24\0\0\0WEBPVP8 ", Some(ImageFormat::WebP)),
+        (b"RIFF\x24\0\0\0WAVEfmt ", None),
+        (b"GIF89a\x01\0\x01\0\0\0", Some(ImageFormat::Gif)),
+        (b"\x89PNG", None),
+        (b"", None),
+    ];
+    for (header, format) in cases {
+        assert_eq!(ImageFormat::from_header(header), format);
+    }
+}
+
+#[test]
+fn ncm_image_format() {
+    // The cover is PNG although the URL says jpg.
+    let ncm = NCMBuilder::new(b"ID3".to_vec(), common::KEY.to_vec())
+        .raw_metadata(br#"{"albumPic":"https://x/y.jpg?param=1"}"#.to_vec())
+        .image(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec())
+        .build();
+    let ncm_file = ncm_parser::from_iter(ncm.clone().into_iter()).unwrap();
+    assert_eq!(ncm_file.image_format(), Some(ImageFormat::Png));
+
+    let ncm = NCMBuilder::new(b"ID3".to_vec(), common::KEY.to_vec()).build();
+    let ncm_file = ncm_parser::from_slice(&ncm).unwrap();
+    assert!(ncm_file.get_image().is_empty());
+    assert_eq!(ncm_file.image_format(), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn reader_image_format() {
+    use std::io::Cursor;
+
+    use ncm_parser::NCMReader;
+
+    let ncm = NCMBuilder::new(b"ID3".to_vec(), common::KEY.to_vec())
+        .image(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec())
+        .build();
+    let ncm_file = ncm_parser::from_reader(Cursor::new(&ncm)).unwrap();
+    assert_eq!(ncm_file.image_format(), Some(ImageFormat::Png));
+    let reader = NCMReader::new(Cursor::new(&ncm)).unwrap();
+    assert_eq!(reader.image_format(), Some(ImageFormat::Png));
+    assert_eq!(reader.get_header().image_format(), Some(ImageFormat::Png));
+}
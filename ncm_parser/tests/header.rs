@@ -0,0 +1,36 @@
+#![cfg(feature = "std")]
+use std::io::Cursor;
+
+use common::build_ncm;
+
+mod common;
+
+#[test]
+fn parse_header_only() {
+    let (ncm, music, metadata, image) = build_ncm();
+
+    let mut cursor = Cursor::new(&ncm);
+    let header = ncm_parser::parse_header(&mut cursor).unwrap();
+    assert_eq!(header.get_metadata().unwrap(), metadata);
+    assert_eq!(header.get_image(), &image);
+    assert_eq!(header.image_len() as usize, image.len());
+    assert_eq!(cursor.position(), header.music_offset());
+    assert_eq!(ncm.len() - music.len(), header.music_offset() as usize);
+
+    let mut cursor = Cursor::new(&ncm);
+    let skipped = ncm_parser::parse_header_skip_image(&mut cursor).unwrap();
+    assert!(skipped.get_image().is_empty());
+    assert_eq!(skipped.image_len(), header.image_len());
+    assert_eq!(cursor.position(), header.music_offset());
+
+    let image_offset = header.image_offset() as usize;
+    assert_eq!(&ncm[image_offset..image_offset + image.len()], image);
+}
+
+#[test]
+fn keep_crc32() {
+    let ncm = common::builder().crc32(0x1234_5678).build();
+    let header = ncm_parser::parse_header_skip_image(Cursor::new(&ncm)).unwrap();
+    assert_eq!(header.crc32(), 0x1234_5678);
+    assert_eq!(ncm_parser::from_slice(&ncm).unwrap().crc32(), 0x1234_5678);
+}
@@ -0,0 +1,39 @@
+mod common;
+
+#[test]
+fn image_frame_larger_than_image() {
+    let (music, image) = (common::music(), common::IMAGE.to_vec());
+    let ncm = common::builder().image_frame_len(1024).build();
+
+    let mut ncm_from_iter = ncm_parser::from_iter(ncm.clone().into_iter()).unwrap();
+    assert_eq!(ncm_from_iter.image_frame_len(), 1024);
+    assert_eq!(ncm_from_iter.get_image().unwrap(), &image);
+    assert_eq!(ncm_from_iter.get_music().unwrap(), &music);
+
+    let ncm_from_slice = ncm_parser::from_slice(&ncm).unwrap();
+    assert_eq!(ncm_from_slice.image_frame_len(), 1024);
+    assert_eq!(ncm_from_slice.get_image(), image);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn reader_image_frame_larger_than_image() {
+    use std::io::Cursor;
+
+    use ncm_parser::NCMReader;
+
+    let (music, image) = (common::music(), common::IMAGE.to_vec());
+    let ncm = common::builder().image_frame_len(1024).build();
+
+    let mut ncm_from_reader = ncm_parser::from_reader(Cursor::new(&ncm)).unwrap();
+    assert_eq!(ncm_from_reader.image_frame_len(), 1024);
+    assert_eq!(ncm_from_reader.get_music().unwrap(), &music);
+
+    let mut reader = NCMReader::new(Cursor::new(&ncm)).unwrap();
+    assert_eq!(reader.get_image(), &image);
+    assert_eq!(reader.decrypt_range(0, 1000).unwrap(), music[..1000]);
+
+    let header = ncm_parser::parse_header_skip_image(Cursor::new(&ncm)).unwrap();
+    assert_eq!(header.image_frame_len(), 1024);
+    assert_eq!(header.music_offset() as usize, ncm.len() - music.len());
+}
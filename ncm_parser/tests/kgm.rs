@@ -0,0 +1,190 @@
+use md5::{Digest, Md5};
+use ncm_parser::{KGMFile, KGMFormat, KGMMask, ParseError, KGM_MAGIC, VPR_MAGIC};
+
+mod common;
+
+fn kugou_md5(data: &[u8]) -> Vec<u8> {
+    let digest = Md5::digest(data);
+    digest.chunks(2).rev().flatten().copied().collect()
+}
+
+/// The header with the audio data starting at 0x400.
+fn build_header(magic: [u8; 16], version: u32, test_data: &[u8; 16]) -> Vec<u8> {
+    let mut kgm = magic.to_vec();
+    kgm.extend_from_slice(&0x400u32.to_le_bytes());
+    kgm.extend_from_slice(&version.to_le_bytes());
+    kgm.extend_from_slice(&1u32.to_le_bytes());
+    kgm.extend_from_slice(test_data);
+    kgm.extend_from_slice(b"0123456789abcdef");
+    kgm.resize(0x400, 0);
+    kgm
+}
+
+/// Encrypt `music` with crypto version 3 and key slot 1.
+fn build_kgm(magic: [u8; 16], music: &[u8]) -> Vec<u8> {
+    let mut file_box = kugou_md5(b"0123456789abcdef");
+    file_box.push(0x6b);
+    let slot_box = kugou_md5(&[0x6c, 0x2c, 0x2f, 0x27]);
+    let vpr_diff = [
+        0x25, 0xdf, 0xe8, 0xa6, 0x75, 0x1e, 0x75, 0x0e, 0x2f, 0x80, 0xf3, 0x2d,
+        0xb8, 0xb6, 0xe3, 0x11, 0x00,
+    ];
+
+    let mut kgm = build_header(magic, 3, &[0; 16]);
+    for (i, x) in music.iter().enumerate() {
+        let [a, b, c, d] = (i as u32).to_le_bytes();
+        let mut t = x ^ a ^ b ^ c ^ d ^ slot_box[i % 16];
+        if magic == VPR_MAGIC {
+            t ^= vpr_diff[i % 17];
+        }
+        kgm.push(t ^ ((t & 0x0f) << 4) ^ file_box[i % 17]);
+    }
+    kgm
+}
+
+/// Decrypt the 16 bytes `0x00..0x10` at `offset` of the audio data.
+fn decrypt_at(kgm: &[u8], offset: u64, mask: Option<&KGMMask>) -> [u8; 16] {
+    let mut kgm = kgm.to_vec();
+    kgm.resize(0x400 + offset as usize, 0);
+    kgm.extend(0..16);
+    let mut kgm_file = match mask {
+        Some(mask) => KGMFile::with_mask(kgm, mask).unwrap(),
+        None => KGMFile::new(kgm).unwrap(),
+    };
+    kgm_file.get_music().unwrap()[offset as usize..]
+        .try_into()
+        .unwrap()
+}
+
+/// The bytes are computed by a separate implementation following the
+/// reference decoders, where the `.vpr` mask is XORed last.
+#[test]
+fn kgm_known_answer() {
+    let kgm = build_header(KGM_MAGIC, 3, &[0; 16]);
+    assert_eq!(
+        decrypt_at(&kgm, 0, None),
+        [
+            0xd8, 0x66, 0x27, 0xd5, 0x95, 0x8b, 0x9f, 0xbf, 0x44, 0xe8, 0xa8, 0xa4,
+            0x14, 0x70, 0xc1, 0x67
+        ]
+    );
+    assert_eq!(
+        decrypt_at(&kgm, 0x12345, None),
+        [
+            0x08, 0xc2, 0xe6, 0x4c, 0xca, 0x05, 0x3b, 0xe3, 0x29, 0xcb, 0x1f, 0x9d,
+            0x4e, 0x66, 0xe2, 0x11
+        ]
+    );
+    let vpr = build_header(VPR_MAGIC, 3, &[0; 16]);
+    assert_eq!(
+        decrypt_at(&vpr, 0, None),
+        [
+            0xfd, 0xb9, 0xcf, 0x73, 0xe0, 0x95, 0xea, 0xb1, 0x6b, 0x68, 0x5b, 0x89,
+            0xac, 0xc6, 0x22, 0x76
+        ]
+    );
+
+    // The older files use the test data as the key, along with the mask table.
+    let mask = KGMMask::new((0..0x20000u32).map(|i| (i * 13 + 7) as u8).collect());
+    let kgm = build_header(KGM_MAGIC, 2, b"fedcba9876543210");
+    assert_eq!(
+        decrypt_at(&kgm, 0, Some(&mask)),
+        [
+            0x71, 0x70, 0x37, 0xae, 0x8d, 0xec, 0xca, 0x8d, 0x50, 0x73, 0xd6, 0x39,
+            0x5c, 0x7f, 0xa2, 0xa5
+        ]
+    );
+    let vpr = build_header(VPR_MAGIC, 2, b"fedcba9876543210");
+    assert_eq!(
+        decrypt_at(&vpr, 0x12345, Some(&mask)),
+        [
+            0xfd, 0xe3, 0xcf, 0xb0, 0x8e, 0x64, 0x06, 0x32, 0x21, 0x0f, 0xa4, 0x1e,
+            0x89, 0xfc, 0x13, 0xee
+        ]
+    );
+}
+
+#[test]
+fn kgm_round_trip() {
+    let music = common::music();
+    for (magic, format) in [(KGM_MAGIC, KGMFormat::Kgm), (VPR_MAGIC, KGMFormat::Vpr)]
+    {
+        let kgm = build_kgm(magic, &music);
+
+        let mut kgm_file = KGMFile::new(kgm.clone()).unwrap();
+        assert_eq!(kgm_file.get_header().format(), format);
+        assert_eq!(kgm_file.get_header().audio_offset(), 0x400);
+        let mut buf = [0; 100];
+        assert_eq!(kgm_file.decrypt_music_into(&mut buf), 100);
+        assert_eq!(buf, music[..100]);
+        assert_eq!(kgm_file.get_music().unwrap(), &music);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn kgm_reader() {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use ncm_parser::KGMReader;
+
+    let music = common::music();
+    for magic in [KGM_MAGIC, VPR_MAGIC] {
+        let kgm = build_kgm(magic, &music);
+
+        let mut kgm_file = KGMFile::from_reader(Cursor::new(&kgm)).unwrap();
+        assert_eq!(kgm_file.get_music().unwrap(), &music);
+
+        let mut reader = KGMReader::new(Cursor::new(&kgm)).unwrap();
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, music);
+        assert_eq!(reader.seek(SeekFrom::Start(5000)).unwrap(), 5000);
+        let mut buf = [0; 100];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, music[5000..5100]);
+        assert!(reader.seek(SeekFrom::Current(-6000)).is_err());
+    }
+}
+
+#[test]
+fn kgm_unsupported() {
+    let mut kgm = build_kgm(KGM_MAGIC, b"music");
+    kgm[0x18] = 2;
+    assert!(matches!(
+        KGMFile::new(kgm).unwrap_err(),
+        ParseError::UnsupportedCrypto {
+            version: 3,
+            slot: 2
+        }
+    ));
+    assert!(matches!(
+        KGMFile::new(vec![0; 0x400]).unwrap_err(),
+        ParseError::InvalidHeader
+    ));
+
+    // The older files can't be decrypted without the mask table, or beyond it.
+    let mut kgm = build_header(KGM_MAGIC, 2, &[0; 16]);
+    assert!(matches!(
+        KGMFile::new(kgm.clone()).unwrap_err(),
+        ParseError::UnsupportedCrypto {
+            version: 2,
+            slot: 1
+        }
+    ));
+    kgm.resize(0x500, 0);
+    let mask = KGMMask::new(vec![0; 0x80]);
+    assert!(matches!(
+        KGMFile::with_mask(kgm.clone(), &mask).unwrap_err(),
+        ParseError::MaskTooShort { len: 0x80 }
+    ));
+    #[cfg(feature = "std")]
+    {
+        use std::io::{Cursor, Read};
+
+        let mut reader =
+            ncm_parser::KGMReader::with_mask(Cursor::new(kgm), &mask).unwrap();
+        let mut buf = [0; 0x100];
+        assert!(reader.read_exact(&mut buf).is_err());
+    }
+}
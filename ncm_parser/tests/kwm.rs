@@ -0,0 +1,115 @@
+use ncm_parser::{KWMFile, ParseError, KWM_MAGIC, KWM_MAGIC_OLD};
+
+mod common;
+
+/// Encrypt `music` with the mask derived from `resource_id`.
+fn build_kwm(magic: [u8; 16], resource_id: u64, music: &[u8]) -> Vec<u8> {
+    let id = resource_id.to_string().into_bytes();
+    let mask: Vec<u8> = b"MoOtOiTvINGwd2E6n0E1i7L5t2IoOoNk"
+        .iter()
+        .zip(id.iter().cycle())
+        .map(|(a, b)| a ^ b)
+        .collect();
+
+    let mut kwm = magic.to_vec();
+    kwm.resize(0x18, 0);
+    kwm.extend_from_slice(&resource_id.to_le_bytes());
+    kwm.resize(0x30, 0);
+    kwm.extend_from_slice(b"320kmp3");
+    kwm.resize(0x400, 0);
+    kwm.extend(music.iter().zip(mask.iter().cycle()).map(|(x, m)| x ^ m));
+    kwm
+}
+
+#[test]
+fn kwm_round_trip() {
+    let music = common::music();
+    for magic in [KWM_MAGIC, KWM_MAGIC_OLD] {
+        let kwm = build_kwm(magic, 156483846, &music);
+
+        let mut kwm_file = KWMFile::new(kwm.clone()).unwrap();
+        assert_eq!(kwm_file.get_header().resource_id(), 156483846);
+        assert_eq!(kwm_file.get_header().bitrate(), 320);
+        assert_eq!(kwm_file.get_header().format(), "mp3");
+        let mut buf = [0; 100];
+        assert_eq!(kwm_file.decrypt_music_into(&mut buf), 100);
+        assert_eq!(buf, music[..100]);
+        assert_eq!(kwm_file.get_music().unwrap(), &music);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn kwm_reader() {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use ncm_parser::KWMReader;
+
+    let music = common::music();
+    for magic in [KWM_MAGIC, KWM_MAGIC_OLD] {
+        let kwm = build_kwm(magic, 156483846, &music);
+
+        let mut kwm_file = KWMFile::from_reader(Cursor::new(&kwm)).unwrap();
+        assert_eq!(kwm_file.get_music().unwrap(), &music);
+
+        let mut reader = KWMReader::new(Cursor::new(&kwm)).unwrap();
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, music);
+        assert_eq!(reader.seek(SeekFrom::Start(5000)).unwrap(), 5000);
+        let mut buf = [0; 100];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, music[5000..5100]);
+        assert!(reader.seek(SeekFrom::Current(-6000)).is_err());
+    }
+}
+
+/// Decrypt the 16 bytes `0x00..0x10` at `offset` of the audio data.
+fn decrypt_at(resource_id: u64, offset: u64) -> [u8; 16] {
+    let mut kwm = build_kwm(KWM_MAGIC, resource_id, &[]);
+    kwm.resize(0x400 + offset as usize, 0);
+    kwm.extend(0..16);
+    let mut kwm_file = KWMFile::new(kwm).unwrap();
+    kwm_file.get_music().unwrap()[offset as usize..]
+        .try_into()
+        .unwrap()
+}
+
+/// The bytes are computed by a separate implementation.
+#[test]
+fn kwm_known_answer() {
+    assert_eq!(
+        decrypt_at(156483846, 0),
+        [
+            0x7c, 0x5b, 0x7b, 0x43, 0x73, 0x5f, 0x6a, 0x45, 0x77, 0x76, 0x78, 0x4a,
+            0x5c, 0x07, 0x78, 0x01
+        ]
+    );
+    assert_eq!(
+        decrypt_at(156483846, 0x12345),
+        [
+            0x5a, 0x6d, 0x40, 0x7c, 0x7b, 0x77, 0x47, 0x57, 0x02, 0x7f, 0x04, 0x51,
+            0x0a, 0x79, 0x0a, 0x50
+        ]
+    );
+    // A single digit id is repeated through the whole key.
+    assert_eq!(
+        decrypt_at(7, 0),
+        [
+            0x7a, 0x59, 0x7a, 0x40, 0x7c, 0x5b, 0x65, 0x46, 0x76, 0x70, 0x7a, 0x4b,
+            0x5f, 0x08, 0x7c, 0x0e
+        ]
+    );
+}
+
+#[test]
+fn kwm_invalid() {
+    assert!(matches!(
+        KWMFile::new(vec![0; 0x400]).unwrap_err(),
+        ParseError::InvalidHeader
+    ));
+    assert!(matches!(
+        KWMFile::new(KWM_MAGIC.to_vec()).unwrap_err(),
+        ParseError::EndOfFile { .. }
+    ));
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "serde")]
+#[cfg(feature = "std")]
+#[test]
+fn round_trip_parsed_metadata() {
+    use std::io::Cursor;
+
+    use ncm_parser::NCMBuilder;
+
+    let metadata = br#"{"musicId":"123","musicName":"test","artist":[["a",1],["b",2]],"albumId":3,"album":"c","albumPicDocId":4,"albumPic":"https://x/y.jpg","bitrate":320000,"mp3DocId":"d","duration":1000,"mvId":0,"alias":["e"],"transNames":[],"format":"mp3","fee":8,"privilege":{"flag":4}}"#;
+    let metadata = ncm_parser::from_iter(
+        NCMBuilder::new(Vec::new(), b"key".to_vec())
+            .raw_metadata(metadata.to_vec())
+            .build()
+            .into_iter(),
+    )
+    .unwrap()
+    .get_parsed_metadata()
+    .unwrap();
+
+    let ncm = NCMBuilder::new(Vec::new(), b"key".to_vec())
+        .metadata(&metadata)
+        .build();
+    let parsed = ncm_parser::from_reader(Cursor::new(ncm))
+        .unwrap()
+        .get_parsed_metadata()
+        .unwrap();
+    assert_eq!(format!("{parsed:?}"), format!("{metadata:?}"));
+    assert_eq!(parsed.artists, [("a".to_string(), 1), ("b".to_string(), 2)]);
+    assert_eq!(parsed.flag, Some(4));
+}
+
+#[test]
+fn lenient_optional_fields() {
+    let metadata = br#"{"musicId":"123","musicName":"test","artist":[],"albumId":3,"album":"c","albumPicDocId":4,"albumPic":"","bitrate":320000,"mp3DocId":123,"duration":1000,"alias":[],"transNames":[],"format":"mp3","fee":"8","flag":-1,"privilege":{"flag":4}}"#;
+    #[allow(deprecated)]
+    let parsed = ncm_parser::NCMMetadata::new(metadata).unwrap();
+    assert_eq!(parsed.mp3_doc_id, None);
+    assert_eq!(parsed.fee, None);
+    // Falls back to `privilege.flag`.
+    assert_eq!(parsed.flag, Some(4));
+
+    let metadata = br#"{"musicId":"123","musicName":"test","artist":[],"albumId":3,"album":"c","albumPicDocId":4,"albumPic":"","bitrate":320000,"mp3DocId":null,"duration":1000,"alias":[],"transNames":[],"format":"mp3","fee":1.5,"privilege":[]}"#;
+    #[allow(deprecated)]
+    let parsed = ncm_parser::NCMMetadata::new(metadata).unwrap();
+    assert_eq!(parsed.mp3_doc_id, None);
+    assert_eq!(parsed.fee, None);
+    assert_eq!(parsed.flag, None);
+}
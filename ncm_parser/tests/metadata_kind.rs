@@ -0,0 +1,98 @@
+use ncm_parser::{MetadataKind, NCMBuilder};
+
+#[test]
+fn empty_metadata() {
+    let ncm = NCMBuilder::new(b"ID3 music".to_vec(), b"key".to_vec()).build();
+
+    let mut ncm_file = ncm_parser::from_iter(ncm.clone().into_iter()).unwrap();
+    assert_eq!(ncm_file.get_metadata_kind().unwrap(), MetadataKind::None);
+    assert!(ncm_file.get_metadata().unwrap().is_empty());
+    assert_eq!(ncm_file.get_music().unwrap(), b"ID3 music");
+
+    let ncm_slice = ncm_parser::from_slice(&ncm).unwrap();
+    assert_eq!(ncm_slice.get_metadata_kind().unwrap(), MetadataKind::None);
+    assert!(ncm_slice.get_metadata().unwrap().is_empty());
+}
+
+#[test]
+fn dj_metadata() {
+    let metadata = br#"{"programName":"p","mainMusic":{"musicName":"m"}}"#.to_vec();
+    let ncm = NCMBuilder::new(Vec::new(), b"key".to_vec())
+        .raw_dj_metadata(metadata.clone())
+        .build();
+
+    let mut ncm_file = ncm_parser::from_iter(ncm.clone().into_iter()).unwrap();
+    assert_eq!(ncm_file.get_metadata_kind().unwrap(), MetadataKind::Dj);
+    assert_eq!(ncm_file.get_metadata().unwrap(), &metadata);
+
+    let ncm_slice = ncm_parser::from_slice(&ncm).unwrap();
+    assert_eq!(ncm_slice.get_metadata_kind().unwrap(), MetadataKind::Dj);
+    assert_eq!(ncm_slice.get_metadata().unwrap(), metadata);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn reader_metadata_kind() {
+    use std::io::Cursor;
+
+    use ncm_parser::NCMReader;
+
+    let ncm = NCMBuilder::new(b"ID3 music".to_vec(), b"key".to_vec()).build();
+    let reader = NCMReader::new(Cursor::new(&ncm)).unwrap();
+    assert_eq!(reader.get_metadata_kind().unwrap(), MetadataKind::None);
+
+    let metadata = br#"{"programName":"p","mainMusic":{"musicName":"m"}}"#.to_vec();
+    let ncm = NCMBuilder::new(Vec::new(), b"key".to_vec())
+        .raw_dj_metadata(metadata.clone())
+        .build();
+    let reader = NCMReader::new(Cursor::new(&ncm)).unwrap();
+    assert_eq!(reader.get_metadata_kind().unwrap(), MetadataKind::Dj);
+    assert_eq!(reader.get_metadata().unwrap(), metadata);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn parsed_dj_metadata() {
+    let metadata = br#"{"programId":1,"programName":"p","mainMusic":{"musicId":"123","musicName":"test","artist":[["a",1]],"albumId":3,"album":"c","albumPicDocId":4,"albumPic":"https://x/y.jpg","bitrate":320000,"mp3DocId":"d","duration":1000,"mvId":0,"alias":[],"transNames":[],"format":"mp3"},"djId":2,"djName":"d","radioId":5,"radioName":"r"}"#;
+    let ncm = NCMBuilder::new(Vec::new(), b"key".to_vec())
+        .raw_dj_metadata(metadata.to_vec())
+        .build();
+
+    let mut ncm_file = ncm_parser::from_iter(ncm.into_iter()).unwrap();
+    let dj = ncm_file.get_parsed_dj_metadata().unwrap();
+    assert_eq!(dj.program_id, "1");
+    assert_eq!(dj.program_name, "p");
+    assert_eq!(dj.dj_name, "d");
+    assert_eq!(dj.radio_id, 5);
+    assert_eq!(dj.main_music.music_name, "test");
+
+    let song = ncm_file.get_parsed_metadata().unwrap();
+    assert_eq!(song.music_id, dj.main_music.music_id);
+
+    let rebuilt = NCMBuilder::new(Vec::new(), b"key".to_vec())
+        .dj_metadata(&dj)
+        .build();
+    let mut rebuilt = ncm_parser::from_iter(rebuilt.into_iter()).unwrap();
+    let rebuilt = rebuilt.get_parsed_dj_metadata().unwrap();
+    assert_eq!(rebuilt.program_name, dj.program_name);
+    assert_eq!(rebuilt.main_music.music_name, dj.main_music.music_name);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn invalid_metadata() {
+    use std::io::Cursor;
+
+    let mut ncm = NCMBuilder::new(b"ID3 music".to_vec(), b"key".to_vec())
+        .raw_metadata(br#"{"musicName":"test"}"#.to_vec())
+        .build();
+    let header = ncm_parser::parse_header(Cursor::new(&ncm)).unwrap();
+    // Not a BASE64 character after the "163 key(Don't modify):" prefix.
+    ncm[header.metadata_offset() as usize + 30] = b'!' ^ 0x63;
+
+    let mut ncm_file = ncm_parser::from_iter(ncm.into_iter()).unwrap();
+    assert!(ncm_file.get_metadata().is_err());
+    // Still fails instead of returning the encrypted metadata.
+    assert!(ncm_file.get_metadata().is_err());
+    assert!(ncm_file.get_metadata_kind().is_err());
+}
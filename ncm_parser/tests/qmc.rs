@@ -0,0 +1,57 @@
+use ncm_parser::{QMCFile, QMCFormat};
+
+mod common;
+
+/// The masks generated by the original seed map algorithm.
+const MASKS: [(u64, [u8; 8]); 3] = [
+    (0, [0xc3, 0x4a, 0xd6, 0xca, 0x90, 0x67, 0xf7, 0x52]),
+    (0x7ffd, [0xca, 0xd6, 0x4a, 0x4a, 0xd6, 0xca, 0x90, 0x67]),
+    (0x12345, [0xf0, 0xd8, 0xf9, 0x66, 0xfa, 0x9f, 0xd5, 0x09]),
+];
+
+#[test]
+fn qmc_masks() {
+    for (offset, masks) in MASKS {
+        let mut buf = [0; 8];
+        ncm_parser::decrypt_qmc_in_place(&mut buf, offset);
+        assert_eq!(buf, masks, "{offset:#x}");
+    }
+}
+
+#[test]
+fn qmc_round_trip() {
+    let music = common::music();
+    let mut qmc = music.clone();
+    ncm_parser::decrypt_qmc_in_place(&mut qmc, 0);
+
+    let mut qmc_file = QMCFile::new(qmc.clone(), QMCFormat::QmcFlac);
+    let mut buf = [0; 100];
+    assert_eq!(qmc_file.decrypt_music_into(&mut buf), 100);
+    assert_eq!(buf, music[..100]);
+    assert_eq!(qmc_file.get_music().unwrap(), &music);
+    assert_eq!(qmc_file.format().music_extension(), "flac");
+
+    assert_eq!(QMCFormat::from_extension("QMCOGG"), Some(QMCFormat::QmcOgg));
+    assert_eq!(QMCFormat::from_extension("ncm"), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn qmc_reader() {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use ncm_parser::QMCReader;
+
+    let music = common::music();
+    let mut qmc = music.clone();
+    ncm_parser::decrypt_qmc_in_place(&mut qmc, 0);
+
+    let mut reader = QMCReader::new(Cursor::new(&qmc), QMCFormat::Qmc0);
+    let mut decrypted = Vec::new();
+    reader.read_to_end(&mut decrypted).unwrap();
+    assert_eq!(decrypted, music);
+    reader.seek(SeekFrom::Start(0x8000)).unwrap();
+    let mut buf = [0; 1000];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, music[0x8000..0x8000 + 1000]);
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "std")]
+use std::io::{Cursor, Read};
+
+use ncm_parser::{NCMReader, ParseError};
+
+mod common;
+
+#[test]
+fn stream_music() {
+    let (ncm, music, metadata, image) = common::build_ncm();
+
+    // A plain `&[u8]` reader, which can't seek.
+    let mut reader = NCMReader::new(ncm.as_slice()).unwrap();
+    assert_eq!(reader.get_metadata().unwrap(), metadata);
+    assert_eq!(reader.get_image(), &image);
+    let mut streamed = Vec::new();
+    let mut buf = [0; 333];
+    loop {
+        let len = reader.read(&mut buf).unwrap();
+        if len == 0 {
+            break;
+        }
+        streamed.extend_from_slice(&buf[..len]);
+    }
+    assert_eq!(streamed, music);
+    assert!(reader.into_inner().is_empty());
+}
+
+#[test]
+fn stream_same_as_from_reader() {
+    let (ncm, _, _, _) = common::build_ncm();
+
+    let mut ncm_file = ncm_parser::from_reader(Cursor::new(&ncm)).unwrap();
+    let mut reader = NCMReader::new(Cursor::new(&ncm)).unwrap();
+    assert_eq!(
+        reader.get_metadata_unchecked(),
+        ncm_file.get_metadata_unchecked()
+    );
+    let mut streamed = Vec::new();
+    reader.read_to_end(&mut streamed).unwrap();
+    assert_eq!(&streamed, ncm_file.get_music().unwrap());
+}
+
+#[test]
+fn stream_invalid_header() {
+    let mut ncm = common::build_ncm().0;
+    ncm[0] = b'X';
+    assert!(matches!(
+        NCMReader::new(Cursor::new(&ncm)),
+        Err(ParseError::InvalidHeader)
+    ));
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "std")]
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use common::build_ncm;
+use ncm_parser::NCMReader;
+
+mod common;
+
+#[test]
+fn seek_and_decrypt_range() {
+    let (ncm, music, _, _) = build_ncm();
+    let mut reader = NCMReader::new(Cursor::new(&ncm)).unwrap();
+
+    assert_eq!(reader.decrypt_range(300, 1000).unwrap(), music[300..1300]);
+    assert_eq!(reader.decrypt_range(99_990, 1000).unwrap(), music[99_990..]);
+    // A huge `len` is not allocated in advance.
+    assert_eq!(
+        reader.decrypt_range(99_990, usize::MAX).unwrap(),
+        music[99_990..]
+    );
+
+    assert_eq!(reader.seek(SeekFrom::End(-10)).unwrap(), 99_990);
+    assert_eq!(reader.seek(SeekFrom::Current(-90)).unwrap(), 99_900);
+    assert!(reader.seek(SeekFrom::Current(-100_000)).is_err());
+    assert_eq!(reader.stream_position().unwrap(), 99_900);
+}
+
+#[test]
+fn read_after_seek() {
+    let (ncm, music, _, _) = build_ncm();
+    let mut reader = NCMReader::new(Cursor::new(&ncm)).unwrap();
+
+    // Across the 256-byte period of the keystream.
+    reader.seek(SeekFrom::Start(1000)).unwrap();
+    let mut buf = [0; 300];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, music[1000..1300]);
+    assert_eq!(reader.stream_position().unwrap(), 1300);
+
+    reader.rewind().unwrap();
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, music);
+}
@@ -0,0 +1,54 @@
+use ncm_parser::{ParseError, Segment};
+
+mod common;
+
+#[test]
+fn from_slice_round_trip() {
+    let (music, metadata, image) =
+        (common::music(), common::METADATA, common::IMAGE);
+    let ncm = common::builder().image_frame_len(100).build();
+
+    let ncm_file = ncm_parser::from_slice(&ncm).unwrap();
+    assert_eq!(ncm_file.get_metadata().unwrap(), metadata);
+    assert_eq!(ncm_file.get_image(), image);
+    assert_eq!(ncm_file.image_frame_len(), 100);
+    // The music is borrowed from the end of the file.
+    assert_eq!(
+        ncm_file.get_music_unchecked(),
+        &ncm[ncm.len() - music.len()..]
+    );
+
+    let mut buf = vec![0; music.len() + 10];
+    assert_eq!(ncm_file.decrypt_music_into(&mut buf).unwrap(), music.len());
+    assert_eq!(buf[..music.len()], music);
+    let mut buf = [0; 1000];
+    assert_eq!(ncm_file.decrypt_music_into(&mut buf).unwrap(), 1000);
+    assert_eq!(buf, music[..1000]);
+
+    let err = ncm_parser::from_slice(&ncm[..5]).unwrap_err();
+    assert!(matches!(
+        err,
+        ParseError::EndOfFile {
+            segment: Segment::Header,
+            offset: 0,
+            expected: 10,
+            available: 5,
+        }
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn raw_segments() {
+    let ncm = common::builder().build();
+    let ncm_file = ncm_parser::from_slice(&ncm).unwrap();
+    let header = ncm_parser::parse_header(ncm.as_slice()).unwrap();
+    let metadata: Vec<u8> = ncm_file
+        .get_metadata_raw()
+        .iter()
+        .map(|x| x ^ 0x63)
+        .collect();
+    assert_eq!(&metadata, header.get_metadata_unchecked());
+    let key = ncm_file.get_rc4_key_raw();
+    assert_eq!(&ncm[14..14 + key.len()], key);
+}
@@ -1,4 +1,5 @@
 #![feature(path_file_prefix)]
+#![cfg(feature = "std")]
 #[test]
 fn try_dump() {
     use std::ffi::OsStr;
@@ -0,0 +1,85 @@
+#![cfg(feature = "serde")]
+use ncm_parser::NCMMetadata;
+use serde_json::{json, Value};
+
+#[test]
+fn keep_unknown_metadata_fields() {
+    let json = br#"{"musicId":123,"musicName":"test","artist":[["a","1"]],"albumId":"3","album":"c","albumPicDocId":4,"albumPic":"","bitrate":320000,"duration":1000,"alias":[],"transNames":[],"format":"flac","privilege":{"flag":4,"maxbr":999000},"newKey":[1,{"x":"y"}]}"#;
+    #[allow(deprecated)]
+    let metadata = NCMMetadata::new(json).unwrap();
+    assert_eq!(metadata.music_id, "123");
+    assert_eq!(metadata.flag, Some(4));
+    assert_eq!(metadata.extra["newKey"], json!([1, {"x": "y"}]));
+    assert_eq!(metadata.extra["privilege"]["maxbr"], 999000);
+    assert!(!metadata.extra.contains_key("flag"));
+
+    // Nothing is changed, and no top-level `flag` is added.
+    let serialized = serde_json::to_value(&metadata).unwrap();
+    let expected: Value = serde_json::from_slice(json).unwrap();
+    assert_eq!(serialized, expected);
+}
+
+#[test]
+fn keep_original_values() {
+    let json = br#"{"musicId":1.5,"musicName":"test","artist":[["a","1","x"],["b",2]],"albumId":"3","album":"c","albumPicDocId":"4","albumPic":"","bitrate":320000,"mp3DocId":7,"duration":1000,"mvId":"invalid","alias":[],"transNames":[],"format":"flac","fee":-1}"#;
+    let original: Value = serde_json::from_slice(json).unwrap();
+    let mut metadata: NCMMetadata =
+        serde_json::from_value(original.clone()).unwrap();
+    assert_eq!(metadata.music_id, "1.5");
+    assert_eq!(metadata.mp3_doc_id, None);
+    assert_eq!(metadata.mv_id, 0);
+    assert_eq!(metadata.fee, None);
+    assert_eq!(serde_json::to_value(&metadata).unwrap(), original);
+
+    // The changed fields are written in their own types.
+    metadata.music_id = "2".to_string();
+    metadata.artists[0].1 = 5;
+    metadata.album_id = 6;
+    metadata.mv_id = 8;
+    let mut expected = original.clone();
+    expected["musicId"] = json!("2");
+    expected["artist"] = json!([["a", 5], ["b", 2]]);
+    expected["albumId"] = json!(6);
+    expected["mvId"] = json!(8);
+    assert_eq!(serde_json::to_value(&metadata).unwrap(), expected);
+}
+
+#[test]
+fn keep_top_level_flag() {
+    let json = br#"{"musicId":"1","musicName":"test","artist":[],"albumId":3,"album":"c","albumPicDocId":4,"albumPic":"","bitrate":320000,"mp3DocId":"d","duration":1000,"mvId":0,"alias":[],"transNames":[],"format":"flac","fee":8}"#;
+    let original: Value = serde_json::from_slice(json).unwrap();
+    for flag in [json!(4), json!("invalid")] {
+        let mut original = original.clone();
+        original["flag"] = flag;
+        original["privilege"] = json!({"flag": 4});
+        let metadata: NCMMetadata =
+            serde_json::from_value(original.clone()).unwrap();
+        assert_eq!(metadata.flag, Some(4));
+        assert_eq!(serde_json::to_value(&metadata).unwrap(), original);
+    }
+
+    // The changed flag is written to the top level.
+    let mut metadata: NCMMetadata = serde_json::from_slice(json).unwrap();
+    assert_eq!(metadata.flag, None);
+    metadata.flag = Some(8);
+    let serialized = serde_json::to_value(&metadata).unwrap();
+    assert_eq!(serialized["flag"], 8);
+    let metadata: NCMMetadata = serde_json::from_value(serialized).unwrap();
+    assert_eq!(metadata.flag, Some(8));
+}
+
+#[test]
+fn serialize_every_field() {
+    // Every field is set to a value other than the default, in the type it
+    // is serialized in, so a field missing from `Serialize` is caught here.
+    let json = br#"{"musicId":"1","musicName":"test","artist":[["a",2]],"albumId":3,"album":"c","albumPicDocId":4,"albumPic":"https://x/y.jpg","bitrate":320000,"mp3DocId":"d","duration":1000,"mvId":5,"alias":["e"],"transNames":["f"],"format":"flac","fee":8,"flag":4}"#;
+    let original: Value = serde_json::from_slice(json).unwrap();
+    let metadata: NCMMetadata = serde_json::from_value(original.clone()).unwrap();
+    // Only the top-level `flag` is kept in `extra`, see `NCMMetadata::flag`.
+    assert_eq!(metadata.extra.keys().collect::<Vec<_>>(), ["flag"]);
+
+    let serialized = serde_json::to_value(&metadata).unwrap();
+    assert_eq!(serialized, original);
+    let parsed: NCMMetadata = serde_json::from_value(serialized).unwrap();
+    assert_eq!(format!("{parsed:?}"), format!("{metadata:?}"));
+}
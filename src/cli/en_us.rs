@@ -1,4 +1,5 @@
 use super::{CLIConfig, Config, ErrMsg};
+use crate::tag::TagMode;
 use clap::Parser;
 
 #[rustfmt::skip]
@@ -32,6 +33,9 @@ pub struct CLI {
         required_unless_present = "filelists",
         help = "\
             Specify paths of *.ncm files or directories containing *.ncm files.\n\
+            Netease cache files (*.uc, *.uc!), QQ Music files (*.qmc0, *.qmc3, \
+            *.qmcflac, *.qmcogg), Kugou Music files (*.kgm, *.vpr) and Kuwo \
+            Music files (*.kwm) are accepted as well.\n\
             Example: -i \"1.ncm\" \"2.ncm\" \"C:\\dir1\" \"D:\\dir2\" ...\n\
         "
     )]
@@ -101,6 +105,87 @@ pub struct CLI {
         "
     )]
     metadata: bool,
+    #[arg(
+        long,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "merge",
+        hide_possible_values = true,
+        help_heading = "OutputFlag",
+        help = "\
+            Write the title, artists, album and cover image into the output \
+            MP3 files as ID3v2.4 tags (with the duration), or FLAC files as \
+            Vorbis comments. <MODE> is \"merge\" by default to keep the other \
+            existing tags, or \"replace\" to drop them.\n\
+            Example: --tag replace
+        "
+    )]
+    tag: Option<TagMode>,
+    #[arg(
+        long,
+        requires = "tag",
+        help_heading = "OutputFlag",
+        help = "\
+            Remove the pictures already in the music when writing tags.
+        "
+    )]
+    strip_covers: bool,
+    #[arg(
+        long,
+        requires = "tag",
+        help_heading = "OutputFlag",
+        help = "\
+            Keep the \"163 key\" of ncm files when writing tags, in the comment \
+            of MP3 files or DESCRIPTION of FLAC files, so that the Netease client \
+            can match the song again.
+        "
+    )]
+    keep_163_key: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help_heading = "Input/Output",
+        help = "\
+            Give the lyric cache directory of the Netease client, where the lyrics \
+            are named by the song id. The lyrics found are saved in .lrc format \
+            next to the output music.\n\
+            Example: --lyrics-dir \"%LOCALAPPDATA%\\Netease\\CloudMusic\\webdata\\lyric\"
+        "
+    )]
+    lyrics_dir: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help_heading = "Input/Output",
+        help = "\
+            Give the mask table to decrypt the older Kugou Music files, which are \
+            not of crypto version 3. The table is not included, use the kgm.mask \
+            file of other decoders. Experimental, not tested with real files.\n\
+            Example: --kgm-mask kgm.mask
+        "
+    )]
+    kgm_mask: Option<String>,
+    #[arg(
+        long,
+        requires = "lyrics_dir",
+        help_heading = "OutputFlag",
+        help = "\
+            Merge the romaji of Japanese lyrics into the .lrc files, under the \
+            original lines of the same time.
+        "
+    )]
+    merge_romaji: bool,
+    #[arg(
+        long,
+        requires = "lyrics_dir",
+        help_heading = "OutputFlag",
+        help = "\
+            Merge the translated lyrics into the .lrc files, under the original \
+            lines of the same time.
+        "
+    )]
+    merge_translation: bool,
     #[arg(
         short,
         long,
@@ -119,6 +204,7 @@ pub struct CLI {
 impl CLIConfig for CLI {
     const ERR_MSG: &'static ErrMsg = &ErrMsg {
         header: "\x1b[1;91mError:\x1b[0m",
+        warning: "\x1b[1;93mWarning:\x1b[0m",
         invalid_utf8: "Found invalid UTF-8/GBK characters",
         get_path_meta: "Failed in reading metadata of path.",
         walkdir: "Failed to read files in directory.",
@@ -130,6 +216,17 @@ impl CLIConfig for CLI {
         saving_meta: "Failed in saving metadata.",
         not_ncm: "This file is not a valid ncm file.",
         parsing_ncm: "Failed in parsing ncm files.",
+        parsing_cache: "Failed in parsing Netease cache files.",
+        parsing_cache_info: "Failed in parsing the info of cache files.",
+        parsing_qmc: "Failed in parsing QQ Music files.",
+        parsing_kgm: "Failed in parsing Kugou Music files.",
+        parsing_kwm: "Failed in parsing Kuwo Music files.",
+        parsing_other: "Failed in parsing the file.",
+        format_mismatch: "The music format differs from the metadata, \
+            saved as the detected one.",
+        tagging: "Failed in writing tags.",
+        parsing_lyrics: "Failed in parsing the lyrics.",
+        saving_lrc: "Failed in saving lyrics.",
 
         ok_msg: "Export Success.",
     };
@@ -162,6 +259,27 @@ impl CLIConfig for CLI {
     fn skip_error(&self) -> bool {
         self.skip_errors
     }
+    fn tag(&self) -> Option<TagMode> {
+        self.tag
+    }
+    fn strip_covers(&self) -> bool {
+        self.strip_covers
+    }
+    fn keep_163_key(&self) -> bool {
+        self.keep_163_key
+    }
+    fn lyrics_dir(&self) -> Option<&String> {
+        self.lyrics_dir.as_ref()
+    }
+    fn kgm_mask(&self) -> Option<&String> {
+        self.kgm_mask.as_ref()
+    }
+    fn merge_romaji(&self) -> bool {
+        self.merge_romaji
+    }
+    fn merge_translation(&self) -> bool {
+        self.merge_translation
+    }
 }
 
 pub fn run() -> Config {
@@ -2,8 +2,13 @@ use std::num::NonZeroU32;
 use std::path::PathBuf;
 
 use super::Config;
+use crate::tag::TagMode;
 use anyhow::Context;
 use encoding_rs::{GBK, UTF_8};
+use ncm_parser::{
+    CacheDecryptor, KGMDecryptor, KGMMask, KWMDecryptor, NCMDecryptor, QMCDecryptor,
+    Registry,
+};
 use walkdir::WalkDir;
 
 pub mod en_us;
@@ -33,6 +38,7 @@ pub fn run() -> Config {
 #[derive(Debug)]
 pub struct ErrMsg {
     pub header: &'static str,
+    pub warning: &'static str,
     pub invalid_utf8: &'static str,
     pub get_path_meta: &'static str,
     pub walkdir: &'static str,
@@ -44,6 +50,16 @@ pub struct ErrMsg {
     pub saving_meta: &'static str,
     pub not_ncm: &'static str,
     pub parsing_ncm: &'static str,
+    pub parsing_cache: &'static str,
+    pub parsing_cache_info: &'static str,
+    pub parsing_qmc: &'static str,
+    pub parsing_kgm: &'static str,
+    pub parsing_kwm: &'static str,
+    pub parsing_other: &'static str,
+    pub format_mismatch: &'static str,
+    pub tagging: &'static str,
+    pub parsing_lyrics: &'static str,
+    pub saving_lrc: &'static str,
 
     pub ok_msg: &'static str,
 }
@@ -72,6 +88,13 @@ trait CLIConfig {
     fn metadata(&self) -> bool;
     fn threads(&self) -> u32;
     fn skip_error(&self) -> bool;
+    fn tag(&self) -> Option<TagMode>;
+    fn strip_covers(&self) -> bool;
+    fn keep_163_key(&self) -> bool;
+    fn lyrics_dir(&self) -> Option<&String>;
+    fn kgm_mask(&self) -> Option<&String>;
+    fn merge_romaji(&self) -> bool;
+    fn merge_translation(&self) -> bool;
 
     fn error(&self, err_msg: std::fmt::Arguments) {
         eprintln!("{} {}", Self::ERR_MSG.header, err_msg);
@@ -86,10 +109,32 @@ trait CLIConfig {
         if self.no_music() == true
             && self.metadata() == false
             && self.cover_img() == false
+            && self.lyrics_dir().is_none()
         {
             self.error(format_args!("{}", err_msg.no_output));
         }
 
+        // The older Kugou Music files are decrypted only with the mask table.
+        let kgm_decryptor = match self.kgm_mask() {
+            Some(file) => match std::fs::read(file)
+                .context(format!("{} [{}]", err_msg.reading_file, file))
+            {
+                Ok(mask) => KGMDecryptor::with_mask(KGMMask::new(mask)),
+                Err(err) => {
+                    self.error(format_args!("{err:?}"));
+                    KGMDecryptor::default()
+                }
+            },
+            None => KGMDecryptor::default(),
+        };
+        let mut registry = Registry::empty();
+        registry
+            .register(NCMDecryptor)
+            .register(kgm_decryptor)
+            .register(KWMDecryptor)
+            .register(QMCDecryptor)
+            .register(CacheDecryptor);
+
         let mut ncm_dirs = Vec::new();
         let mut ncm_files = Vec::new();
 
@@ -185,16 +230,19 @@ trait CLIConfig {
                 }
             };
 
+            // Only pick the files by their extensions instead of opening every
+            // file in the directories. The format is sniffed when dumping.
             ncm_files.extend(
                 files
                     .into_iter()
                     .map(|entry| entry.into_path())
-                    .filter(|path| path.extension() == Some("ncm".as_ref())),
+                    .filter(|path| crate::dump::known_extension(&registry, path)),
             );
         }
 
         return Config {
             err_msg,
+            registry,
             ncm_files,
             output_dir: self.output_dir().as_ref().map(|path| PathBuf::from(path)),
             threads: NonZeroU32::new(self.threads()),
@@ -202,6 +250,12 @@ trait CLIConfig {
             with_music: !self.no_music(),
             with_image: self.cover_img(),
             with_metadata: self.metadata(),
+            tag: self.tag(),
+            strip_covers: self.strip_covers(),
+            keep_163_key: self.keep_163_key(),
+            lyrics_dir: self.lyrics_dir().map(PathBuf::from),
+            merge_romaji: self.merge_romaji(),
+            merge_translation: self.merge_translation(),
         };
     }
 }
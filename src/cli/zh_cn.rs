@@ -1,4 +1,5 @@
 use super::{CLIConfig, Config, ErrMsg};
+use crate::tag::TagMode;
 use clap::Parser;
 
 #[rustfmt::skip]
@@ -31,6 +32,8 @@ pub struct CLI {
         required_unless_present = "filelists",
         help = "\
             输入 .ncm 文件的路径或包含 .ncm 文件的目录。\n\
+            同时支持网易云音乐的缓存文件（.uc 和 .uc!）、QQ 音乐的 \
+            .qmc0、.qmc3、.qmcflac 和 .qmcogg 文件、酷狗音乐的 .kgm 和 .vpr 文件以及酷我音乐的 .kwm 文件。\n\
             例如：-i \"1.ncm\" \"2.ncm\" \"C:\\dir1\" \"D:\\dir2\" ...\n\
         "
     )]
@@ -95,6 +98,81 @@ pub struct CLI {
         "
     )]
     metadata: bool,
+    #[arg(
+        long,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "merge",
+        hide_possible_values = true,
+        help_heading = "OutputFlag",
+        help = "\
+            将标题、歌手、专辑和封面写入导出的音频文件，MP3 文件写入 ID3v2.4 \
+            标签（含时长），FLAC 文件写入 Vorbis 注释。\
+            <MODE> 默认为 \"merge\"，保留其余已有的标签；\"replace\" 则将其删除\n\
+            例如：--tag replace
+        "
+    )]
+    tag: Option<TagMode>,
+    #[arg(
+        long,
+        requires = "tag",
+        help_heading = "OutputFlag",
+        help = "\
+            写入标签时删除音频中已有的图片
+        "
+    )]
+    strip_covers: bool,
+    #[arg(
+        long,
+        requires = "tag",
+        help_heading = "OutputFlag",
+        help = "\
+            写入标签时保留 ncm 文件的 \"163 key\"，存于 MP3 文件的注释或 FLAC 文件的 \
+            DESCRIPTION 中，以便网易云音乐客户端重新匹配歌曲
+        "
+    )]
+    keep_163_key: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help_heading = "Input/Output",
+        help = "\
+            指定网易云音乐客户端的歌词缓存目录，其中的歌词以歌曲 ID 命名。\
+            找到的歌词将以 .lrc 格式保存在导出的音频旁\n\
+            例如：--lyrics-dir \"%LOCALAPPDATA%\\Netease\\CloudMusic\\webdata\\lyric\"
+        "
+    )]
+    lyrics_dir: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help_heading = "Input/Output",
+        help = "\
+            指定用于解密旧版酷狗音乐文件（非加密版本 3）的掩码表。本程序不附带掩码表，\
+            请使用其他解密工具的 kgm.mask 文件。实验性功能，未经真实文件测试\n\
+            例如：--kgm-mask kgm.mask
+        "
+    )]
+    kgm_mask: Option<String>,
+    #[arg(
+        long,
+        requires = "lyrics_dir",
+        help_heading = "OutputFlag",
+        help = "\
+            将日语歌词的罗马音合并到 .lrc 文件中，置于同一时间的原歌词之后
+        "
+    )]
+    merge_romaji: bool,
+    #[arg(
+        long,
+        requires = "lyrics_dir",
+        help_heading = "OutputFlag",
+        help = "\
+            将翻译歌词合并到 .lrc 文件中，置于同一时间的原歌词之后
+        "
+    )]
+    merge_translation: bool,
 
     #[arg(
         short,
@@ -113,6 +191,7 @@ pub struct CLI {
 impl CLIConfig for CLI {
     const ERR_MSG: &'static ErrMsg = &ErrMsg {
         header: "\x1b[1;91m错误:\x1b[0m",
+        warning: "\x1b[1;93m警告:\x1b[0m",
         invalid_utf8: "文件中包含非 UTF-8/GBK 字符",
         get_path_meta: "读取路径信息时发生错误：",
         walkdir: "无法读取路径下的文件：",
@@ -124,6 +203,16 @@ impl CLIConfig for CLI {
         saving_meta: "保存文件元信息时出错：",
         not_ncm: "不是 ncm 文件。",
         parsing_ncm: "解析 ncm 文件时出现错误：",
+        parsing_cache: "解析网易云音乐缓存文件时出现错误：",
+        parsing_cache_info: "解析缓存文件信息时出现错误：",
+        parsing_qmc: "解析 QQ 音乐文件时出现错误：",
+        parsing_kgm: "解析酷狗音乐文件时出现错误：",
+        parsing_kwm: "解析酷我音乐文件时出现错误：",
+        parsing_other: "解析文件时出现错误：",
+        format_mismatch: "音乐格式与元信息不符，已按检测到的格式保存：",
+        tagging: "写入标签时出现错误：",
+        parsing_lyrics: "解析歌词时出现错误：",
+        saving_lrc: "保存歌词时出错：",
 
         ok_msg: "导出成功。",
     };
@@ -155,6 +244,27 @@ impl CLIConfig for CLI {
     fn skip_error(&self) -> bool {
         self.skip_errors
     }
+    fn tag(&self) -> Option<TagMode> {
+        self.tag
+    }
+    fn strip_covers(&self) -> bool {
+        self.strip_covers
+    }
+    fn keep_163_key(&self) -> bool {
+        self.keep_163_key
+    }
+    fn lyrics_dir(&self) -> Option<&String> {
+        self.lyrics_dir.as_ref()
+    }
+    fn kgm_mask(&self) -> Option<&String> {
+        self.kgm_mask.as_ref()
+    }
+    fn merge_romaji(&self) -> bool {
+        self.merge_romaji
+    }
+    fn merge_translation(&self) -> bool {
+        self.merge_translation
+    }
 }
 
 pub fn run() -> Config {
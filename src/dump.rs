@@ -1,86 +1,263 @@
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use ncm_parser::{self, ParseError};
+use ncm_parser::{
+    AudioFormat, CacheInfo, Decryptor, DecryptorKind, EncryptedTrackExt,
+    ImageFormat, ParseError, Registry,
+};
 
 use crate::cli::ErrMsg;
+use crate::tag::{self, Tags};
+use crate::{lyrics, Config};
 
 pub fn dump(
-    err_msg: &ErrMsg,
+    cfg: &Config,
     file: &'static PathBuf,
-    out_dir: Option<&PathBuf>,
-    with_music: bool,
-    with_image: bool,
-    with_metadata: bool,
 ) -> Result<(&'static str, &'static PathBuf)> {
-    let in_file = File::open(&file).context(format!(
+    let err_msg = cfg.err_msg;
+    // Report the files not supported as invalid ncm files.
+    let Some(decryptor) = detect(&cfg.registry, file) else {
+        Err(ParseError::InvalidHeader).context(format!(
+            "{} [{}]",
+            err_msg.not_ncm,
+            file.display()
+        ))?
+    };
+    let is_cache = decryptor.kind() == DecryptorKind::Cache;
+    let parsing_msg = match decryptor.kind() {
+        DecryptorKind::Ncm => err_msg.parsing_ncm,
+        DecryptorKind::Kgm => err_msg.parsing_kgm,
+        DecryptorKind::Kwm => err_msg.parsing_kwm,
+        DecryptorKind::Qmc => err_msg.parsing_qmc,
+        DecryptorKind::Cache => err_msg.parsing_cache,
+        _ => err_msg.parsing_other,
+    };
+
+    let in_file = File::open(file).context(format!(
         "{} [{}]",
         err_msg.reading_file,
         file.display()
     ))?;
 
-    let mut ncm = match ncm_parser::from_reader(in_file) {
-        Ok(ncm) => ncm,
-        err @ Err(ParseError::InvalidHeader) => {
-            err.context(format!("{} [{}]", err_msg.not_ncm, file.display()))?
-        }
-        err @ _ => {
-            err.context(format!("{} [{}]", err_msg.parsing_ncm, file.display()))?
+    let mut track = match decryptor.open(Box::new(in_file), extension(file)) {
+        Ok(track) => track,
+        Err(ParseError::InvalidHeader) => Err(ParseError::InvalidHeader)
+            .context(format!("{} [{}]", err_msg.not_ncm, file.display()))?,
+        Err(err) => {
+            Err(err).context(format!("{} [{}]", parsing_msg, file.display()))?
         }
     };
-    
-    let out_file_exts_with_ncm = match out_dir {
+
+    let out_file_exts_with_ncm = match &cfg.output_dir {
         Some(out_dir) => out_dir.join(file.file_name().unwrap()),
         None => file.clone(),
     };
-    
-    let metadata = ncm.get_parsed_metadata().context(format!(
-        "{} [{}]",
-        err_msg.parsing_ncm,
-        file.display()
-    ))?;
-    
-    if with_music {
-        let music = ncm.get_music().context(format!(
+
+    let metadata = track.metadata();
+    let metadata =
+        metadata.context(format!("{} [{}]", parsing_msg, file.display()))?;
+    // The metadata in the format of ncm files tells the format of the music.
+    // The song of radio programs is parsed as well.
+    let parsed_metadata = track.parsed_metadata();
+    let parsed_metadata =
+        parsed_metadata.context(format!("{} [{}]", parsing_msg, file.display()))?;
+
+    if cfg.with_music {
+        let mut music = track.music().context(format!(
             "{} [{}]",
-            err_msg.parsing_ncm,
+            parsing_msg,
             file.display()
         ))?;
+        let mut music_header = Vec::new();
+        // Read past the ID3v2 tag in front of the music, if any.
+        let mut sniff_len = AudioFormat::SNIFF_LEN;
+        while music_header.len() < sniff_len {
+            let len = (sniff_len - music_header.len()) as u64;
+            let read = music
+                .by_ref()
+                .take(len)
+                .read_to_end(&mut music_header)
+                .context(format!("{} [{}]", err_msg.reading_file, file.display()))?;
+            if read == 0 {
+                break;
+            }
+            sniff_len = AudioFormat::sniff_len(&music_header);
+        }
+        let format = AudioFormat::from_header(&music_header);
+        let claimed = parsed_metadata
+            .as_ref()
+            .map(|metadata| metadata.format.as_str())
+            .filter(|claimed| !claimed.is_empty());
+        let ext = match (format, claimed) {
+            (Some(format), Some(claimed))
+                if !format.extension().eq_ignore_ascii_case(claimed) =>
+            {
+                eprintln!(
+                    "{} {} [{}] [{} -> {}]",
+                    err_msg.warning,
+                    err_msg.format_mismatch,
+                    file.display(),
+                    claimed,
+                    format.extension()
+                );
+                format.extension()
+            }
+            (Some(format), _) => format.extension(),
+            (None, Some(claimed)) => claimed,
+            // Most of these files are in MP3 format.
+            (None, None) => "mp3",
+        };
+        let out_file = out_file_exts_with_ncm.with_extension(ext);
+        File::create(&out_file)
+            .and_then(|mut out| {
+                out.write_all(&music_header)?;
+                std::io::copy(&mut music, &mut out)
+            })
+            .context(format!("{} [{}]", err_msg.saving_ncm, out_file.display()))?;
+        // The music borrows the track, which is needed for the cover.
+        drop(music);
 
-        let out_file = out_file_exts_with_ncm.with_extension(metadata.format);
-        std::fs::write(&out_file, music).context(format!(
-            "{} [{}]",
-            err_msg.saving_ncm,
-            out_file.display()
-        ))?;
+        if let Some(mode) = cfg.tag {
+            let tags = Tags {
+                metadata: parsed_metadata.as_ref(),
+                cover: track.cover(),
+                key: track.raw_metadata().filter(|_| cfg.keep_163_key),
+            };
+            let tagging_msg =
+                format!("{} [{}]", err_msg.tagging, out_file.display());
+            match ext {
+                "mp3" => tag::write_id3(&out_file, mode, cfg.strip_covers, &tags)
+                    .context(tagging_msg)?,
+                "flac" => tag::write_flac(&out_file, mode, cfg.strip_covers, &tags)
+                    .context(tagging_msg)?,
+                // Other formats are saved as they are.
+                _ => {}
+            }
+        }
     }
 
-    if with_image {
-        let image = ncm.get_image().into_ok();
-        let out_file = out_file_exts_with_ncm.with_extension(
-            metadata
-                .album_pic_url
-                .rsplit_once('.')
-                .context(format!("{} [{}]", err_msg.saving_img, file.display()))?
-                .1,
-        );
-        std::fs::write(&out_file, image).context(format!(
-            "{} [{}]",
-            err_msg.saving_img,
-            out_file.display()
-        ))?;
+    if cfg.with_image {
+        // Some files have an empty cover image segment.
+        if let Some(image) = track.cover().filter(|image| !image.is_empty()) {
+            // The covers are mostly in JPEG format.
+            let ext = ImageFormat::from_header(image)
+                .map_or("jpg", |format| format.extension());
+            let out_file = out_file_exts_with_ncm.with_extension(ext);
+            std::fs::write(&out_file, image).context(format!(
+                "{} [{}]",
+                err_msg.saving_img,
+                out_file.display()
+            ))?;
+        }
     }
 
-    if with_metadata {
-        let metadata = ncm.get_metadata_unchecked();
-        let out_file = out_file_exts_with_ncm.with_extension("json");
-        std::fs::write(&out_file, metadata).context(format!(
-            "{} [{}]",
-            err_msg.saving_meta,
-            out_file.display()
-        ))?;
+    if cfg.with_metadata {
+        // Cache files keep their info in other files.
+        let metadata = match metadata {
+            None if is_cache => cache_info(err_msg, file)?,
+            metadata => metadata,
+        };
+        if let Some(metadata) = metadata {
+            let out_file = out_file_exts_with_ncm.with_extension("json");
+            std::fs::write(&out_file, metadata).context(format!(
+                "{} [{}]",
+                err_msg.saving_meta,
+                out_file.display()
+            ))?;
+        }
+    }
+
+    if let Some(lyrics_dir) = &cfg.lyrics_dir {
+        let lyrics_file = parsed_metadata
+            .as_ref()
+            .map(|metadata| metadata.music_id.as_str())
+            .filter(|music_id| !music_id.is_empty())
+            .and_then(|music_id| lyrics::find(lyrics_dir, music_id));
+        // Not every song has its lyrics cached.
+        if let Some(lyrics_file) = lyrics_file {
+            let lyrics = std::fs::read(&lyrics_file).context(format!(
+                "{} [{}]",
+                err_msg.reading_file,
+                lyrics_file.display()
+            ))?;
+            let lrc =
+                lyrics::to_lrc(&lyrics, cfg.merge_romaji, cfg.merge_translation)
+                    .context(format!(
+                        "{} [{}]",
+                        err_msg.parsing_lyrics,
+                        lyrics_file.display()
+                    ))?;
+            if let Some(lrc) = lrc {
+                let out_file = out_file_exts_with_ncm.with_extension("lrc");
+                std::fs::write(&out_file, lrc).context(format!(
+                    "{} [{}]",
+                    err_msg.saving_lrc,
+                    out_file.display()
+                ))?;
+            }
+        }
     }
 
     Ok((err_msg.ok_msg, file))
 }
+
+/// Pick the format of `file` from the registry by its magic header, or by
+/// its extension if no format claims the magic header.
+pub fn detect<'a>(registry: &'a Registry, file: &Path) -> Option<&'a dyn Decryptor> {
+    let mut magic = Vec::new();
+    // The magic header is left empty if the file can't be read, which
+    // would be reported when dumping.
+    let _ = File::open(file).and_then(|file| file.take(16).read_to_end(&mut magic));
+    registry.find(&magic, extension(file))
+}
+
+/// Whether any format in the registry claims the extension of `file`,
+/// without opening it like [`detect()`].
+pub fn known_extension(registry: &Registry, file: &Path) -> bool {
+    let extension = extension(file);
+    registry
+        .iter()
+        .any(|decryptor| decryptor.probe_extension(extension))
+}
+
+fn extension(file: &Path) -> &str {
+    file.extension().and_then(|ext| ext.to_str()).unwrap_or("")
+}
+
+/// Read the info of the cache file from the `.idx!` or `.info` file next to
+/// it, or from its name, then convert it to JSON.
+fn cache_info(err_msg: &ErrMsg, file: &Path) -> Result<Option<Vec<u8>>> {
+    // The info is stored in `.idx!` by PC clients, and `.info` by others.
+    let info_file = ["idx!", "info"]
+        .into_iter()
+        .map(|ext| file.with_extension(ext))
+        .find(|info_file| info_file.is_file());
+    let info = match info_file {
+        Some(info_file) => {
+            let info = std::fs::read(&info_file).context(format!(
+                "{} [{}]",
+                err_msg.reading_file,
+                info_file.display()
+            ))?;
+            Some(CacheInfo::new(&info).context(format!(
+                "{} [{}]",
+                err_msg.parsing_cache_info,
+                info_file.display()
+            ))?)
+        }
+        None => file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(CacheInfo::from_file_name),
+    };
+
+    info.map(|info| serde_json::to_vec(&info))
+        .transpose()
+        .context(format!(
+            "{} [{}]",
+            err_msg.parsing_cache_info,
+            file.display()
+        ))
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+/// Find the lyrics of the song in the lyric cache directory of the Netease
+/// client, where the files are named by the song id.
+pub fn find(dir: &Path, music_id: &str) -> Option<PathBuf> {
+    // The PC client names them without any extension.
+    [music_id.to_string(), format!("{music_id}.json")]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Convert the cached lyrics in JSON format to LRC format. The lines in
+/// `romalrc` (romaji of Japanese songs) and `tlyric` (translation) are
+/// merged under the original ones of the same time, in this order, if
+/// `merge_romaji` and `merge_translation` are set respectively. Returns
+/// `None` if the song has no lyrics.
+pub fn to_lrc(
+    lyrics: &[u8],
+    merge_romaji: bool,
+    merge_translation: bool,
+) -> serde_json::Result<Option<String>> {
+    let lyrics: Value = serde_json::from_slice(lyrics)?;
+    let lyric = lyrics["lyric"].as_str().unwrap_or_default();
+    if lyric.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let merged: Vec<_> = [("romalrc", merge_romaji), ("tlyric", merge_translation)]
+        .into_iter()
+        .filter(|(_, merge)| *merge)
+        .map(|(key, _)| lines_by_time(lyrics[key].as_str().unwrap_or_default()))
+        .collect();
+
+    let mut lrc = String::new();
+    for line in lyric.lines() {
+        lrc.push_str(line);
+        lrc.push('\n');
+        // Same time tags as the original line, so that players show them
+        // together.
+        let (tags, times) = split_time_tags(line);
+        let Some(time) = times.first() else {
+            continue;
+        };
+        for text in merged.iter().filter_map(|lines| lines.get(time)) {
+            lrc.push_str(tags);
+            lrc.push_str(text);
+            lrc.push('\n');
+        }
+    }
+    Ok(Some(lrc))
+}
+
+/// Map the times of the non-empty lines to their text.
+fn lines_by_time(lyric: &str) -> HashMap<u64, &str> {
+    let mut lines = HashMap::new();
+    for line in lyric.lines() {
+        let (tags, times) = split_time_tags(line);
+        let text = line[tags.len()..].trim();
+        if !text.is_empty() {
+            lines.extend(times.into_iter().map(|time| (time, text)));
+        }
+    }
+    lines
+}
+
+/// Split the time tags such as `[01:23.45]` off the front of the line, and
+/// parse them into milliseconds. Other tags such as `[ti:xxx]` are not.
+fn split_time_tags(line: &str) -> (&str, Vec<u64>) {
+    let mut rest = line;
+    let mut times = Vec::new();
+    while let Some((tag, after)) =
+        rest.strip_prefix('[').and_then(|x| x.split_once(']'))
+    {
+        let Some(time) = parse_time(tag) else {
+            break;
+        };
+        times.push(time);
+        rest = after;
+    }
+    (&line[..line.len() - rest.len()], times)
+}
+
+/// Parse `mm:ss.xx` or `mm:ss.xxx` into milliseconds, so that the time tags
+/// with different precisions still match.
+fn parse_time(tag: &str) -> Option<u64> {
+    let (min, sec) = tag.split_once(':')?;
+    let (sec, frac) = sec.split_once('.').unwrap_or((sec, "0"));
+    let min: u64 = min.parse().ok()?;
+    let sec: u64 = sec.parse().ok()?;
+    if !frac.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+    let ms: u64 = format!("{frac:0<3}")[..3].parse().ok()?;
+    Some((min * 60 + sec) * 1000 + ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lrc(lyrics: Value, romaji: bool, translation: bool) -> Option<String> {
+        to_lrc(&serde_json::to_vec(&lyrics).unwrap(), romaji, translation).unwrap()
+    }
+
+    #[test]
+    fn parse_time_precisions() {
+        assert_eq!(parse_time("01:23.45"), Some(83_450));
+        assert_eq!(parse_time("01:23.450"), Some(83_450));
+        assert_eq!(parse_time("01:23.456"), Some(83_456));
+        assert_eq!(parse_time("1:02.5"), Some(62_500));
+        assert_eq!(parse_time("01:23"), Some(83_000));
+        assert_eq!(parse_time("ti:title"), None);
+        assert_eq!(parse_time("01:23.4x"), None);
+        assert_eq!(parse_time("offset"), None);
+    }
+
+    #[test]
+    fn split_multiple_tags() {
+        let line = "[00:01.00][00:02.500][01:00.12]chorus";
+        assert_eq!(
+            split_time_tags(line),
+            (
+                "[00:01.00][00:02.500][01:00.12]",
+                vec![1_000, 2_500, 60_120]
+            )
+        );
+        // The other tags after the time tags are left in the text.
+        assert_eq!(
+            split_time_tags("[00:01.00][by:someone]text"),
+            ("[00:01.00]", vec![1_000])
+        );
+        assert_eq!(split_time_tags("[ti:title]"), ("", vec![]));
+        assert_eq!(split_time_tags("no tags"), ("", vec![]));
+        assert_eq!(split_time_tags(""), ("", vec![]));
+    }
+
+    #[test]
+    fn empty_lyric() {
+        let tlyric = "[00:01.00]translation";
+        assert_eq!(lrc(serde_json::json!({ "lyric": "" }), true, true), None);
+        assert_eq!(
+            lrc(
+                serde_json::json!({ "lyric": " \n", "tlyric": tlyric }),
+                true,
+                true
+            ),
+            None
+        );
+        assert_eq!(
+            lrc(serde_json::json!({ "tlyric": tlyric }), true, true),
+            None
+        );
+        assert_eq!(lrc(serde_json::json!({ "lyric": null }), true, true), None);
+    }
+
+    #[test]
+    fn merge_mixed_precisions() {
+        let lyrics = serde_json::json!({
+            "lyric": "[00:01.50]one\n[00:02.123]two\n[00:03.00]three",
+            "tlyric": "[00:01.500]一\n[00:02.12]二\n[00:03.000]",
+        });
+        assert_eq!(
+            lrc(lyrics, false, true).unwrap(),
+            "[00:01.50]one\n[00:01.50]一\n[00:02.123]two\n[00:03.00]three\n"
+        );
+    }
+
+    #[test]
+    fn merge_multi_tag_lines() {
+        let lyrics = serde_json::json!({
+            "lyric": "[00:01.00][00:05.00]chorus\n[00:03.00]verse",
+            "tlyric": "[00:01.000][00:05.000]副歌\n[00:03.00]主歌",
+        });
+        assert_eq!(
+            lrc(lyrics, false, true).unwrap(),
+            concat!(
+                "[00:01.00][00:05.00]chorus\n[00:01.00][00:05.00]副歌\n",
+                "[00:03.00]verse\n[00:03.00]主歌\n",
+            )
+        );
+    }
+
+    #[test]
+    fn keep_headers() {
+        let lyrics = serde_json::json!({
+            "lyric": "[ti:title]\n[ar:artist]\n[00:01.00]line",
+            "tlyric": "[ti:标题]\n[by:someone]\n[00:01.00]行",
+        });
+        assert_eq!(
+            lrc(lyrics, false, true).unwrap(),
+            "[ti:title]\n[ar:artist]\n[00:01.00]line\n[00:01.00]行\n"
+        );
+    }
+
+    #[test]
+    fn merge_romaji_and_translation() {
+        let lyrics = serde_json::json!({
+            "lyric": "[00:01.00]歌",
+            "tlyric": "[00:01.00]song",
+            "romalrc": "[00:01.00]uta",
+        });
+        assert_eq!(lrc(lyrics.clone(), false, false).unwrap(), "[00:01.00]歌\n");
+        assert_eq!(
+            lrc(lyrics.clone(), true, false).unwrap(),
+            "[00:01.00]歌\n[00:01.00]uta\n"
+        );
+        assert_eq!(
+            lrc(lyrics, true, true).unwrap(),
+            "[00:01.00]歌\n[00:01.00]uta\n[00:01.00]song\n"
+        );
+    }
+}
@@ -1,15 +1,17 @@
 #![feature(iterator_try_collect)]
-#![feature(unwrap_infallible)]
 use rusty_pool;
 use std::num::NonZeroU32;
 use std::path::PathBuf;
 
 mod cli;
 mod dump;
+mod lyrics;
+mod tag;
 
 #[derive(Debug)]
 pub struct Config {
     pub err_msg: &'static cli::ErrMsg,
+    pub registry: ncm_parser::Registry,
     pub ncm_files: Vec<PathBuf>,
     pub output_dir: Option<PathBuf>,
     pub threads: Option<NonZeroU32>,
@@ -17,6 +19,12 @@ pub struct Config {
     pub with_music: bool,
     pub with_image: bool,
     pub with_metadata: bool,
+    pub tag: Option<tag::TagMode>,
+    pub strip_covers: bool,
+    pub keep_163_key: bool,
+    pub lyrics_dir: Option<PathBuf>,
+    pub merge_romaji: bool,
+    pub merge_translation: bool,
 }
 
 fn main() {
@@ -31,16 +39,7 @@ fn main() {
 
     let mut tasks = Vec::new();
     for file in cfg.ncm_files.iter() {
-        let task = || {
-            dump::dump(
-                cfg.err_msg,
-                file,
-                cfg.output_dir.as_ref(),
-                cfg.with_music,
-                cfg.with_image,
-                cfg.with_metadata,
-            )
-        };
+        let task = || dump::dump(cfg, file);
         tasks.push(thread_pool.evaluate(task));
     }
 
@@ -0,0 +1,694 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use id3::frame::{Comment, Picture, PictureType};
+use id3::{Tag, TagLike, Version};
+use ncm_parser::{AudioFormat, ImageFormat, NCMMetadata};
+
+/// How to deal with the tags already in the music.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TagMode {
+    /// Keep the existing frames, except those written from the metadata.
+    Merge,
+    /// Drop the existing frames.
+    Replace,
+}
+
+/// What to write into the music.
+pub struct Tags<'a> {
+    pub metadata: Option<&'a NCMMetadata>,
+    pub cover: Option<&'a [u8]>,
+    /// The `163 key(Don't modify):...` string of ncm files, written as the
+    /// comment like the Netease client does.
+    pub key: Option<&'a [u8]>,
+}
+
+/// Write the title, artists, album, duration, cover image and key into the
+/// MP3 file as ID3v2.4 tags. The fields missing in `tags` are left as they
+/// are.
+pub fn write_id3(
+    path: &Path,
+    mode: TagMode,
+    strip_covers: bool,
+    tags: &Tags,
+) -> id3::Result<()> {
+    let mut tag = match mode {
+        // A broken tag is still merged as far as it can be read.
+        TagMode::Merge => {
+            id3::no_tag_ok(id3::partial_tag_ok(Tag::read_from_path(path)))?
+                .unwrap_or_default()
+        }
+        TagMode::Replace => Tag::new(),
+    };
+    if strip_covers {
+        tag.remove_all_pictures();
+    }
+
+    if let Some(metadata) = tags.metadata {
+        if !metadata.music_name.is_empty() {
+            tag.set_title(&metadata.music_name);
+        }
+        if !metadata.artists.is_empty() {
+            // Multiple artists are separated by null characters in ID3v2.4.
+            let artists = metadata.artists.iter().map(|(name, _)| name.as_str());
+            tag.set_text_values("TPE1", artists);
+        }
+        if !metadata.album_name.is_empty() {
+            tag.set_album(&metadata.album_name);
+        }
+        // The duration is in milliseconds, same as TLEN.
+        if let Ok(duration @ 1..) = u32::try_from(metadata.duration) {
+            tag.set_duration(duration);
+        }
+    }
+
+    if let Some(cover) = tags.cover.filter(|cover| !cover.is_empty()) {
+        tag.remove_picture_by_type(PictureType::CoverFront);
+        tag.add_frame(Picture {
+            mime_type: cover_mime_type(cover).to_string(),
+            picture_type: PictureType::CoverFront,
+            description: String::new(),
+            data: cover.to_vec(),
+        });
+    }
+
+    if let Some(key) = tags.key {
+        // Same as the Netease client, which replaces the comment of the
+        // same language and description.
+        tag.add_frame(Comment {
+            lang: "XXX".to_string(),
+            description: String::new(),
+            text: String::from_utf8_lossy(key).into_owned(),
+        });
+    }
+
+    tag.write_to_path(path, Version::Id3v24)
+}
+
+// The types of FLAC metadata blocks.
+const STREAMINFO: u8 = 0;
+const PADDING: u8 = 1;
+const VORBIS_COMMENT: u8 = 4;
+const PICTURE: u8 = 6;
+
+/// A FLAC metadata block as `(type, data)`.
+type Block = (u8, Vec<u8>);
+
+/// The picture type of the front cover, shared by ID3 and FLAC.
+const FRONT_COVER: u32 = 3;
+
+/// The padding left for editing the tags later when the whole file has to
+/// be rewritten, same as `metaflac`.
+const DEFAULT_PADDING: u64 = 8192;
+
+/// Write the title, artists, album, key and cover image into the FLAC file
+/// as VORBIS_COMMENT and PICTURE blocks. The audio frames are untouched, and
+/// the file is only rewritten if the existing padding is not enough. An ID3v2
+/// tag in front of `fLaC`, which some ncm files have, is kept as it is.
+pub fn write_flac(
+    path: &Path,
+    mode: TagMode,
+    strip_covers: bool,
+    tags: &Tags,
+) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let (old_blocks, flac_offset, audio_offset) = read_flac_blocks(&mut file)?;
+    let cover = tags.cover.filter(|cover| !cover.is_empty());
+
+    let mut vendor = None;
+    let mut comments = Vec::new();
+    let mut blocks = Vec::new();
+    for (block_type, data) in old_blocks {
+        match block_type {
+            VORBIS_COMMENT => {
+                let (old_vendor, old_comments) = parse_vorbis_comment(&data)?;
+                vendor = Some(old_vendor);
+                if mode == TagMode::Merge {
+                    comments = old_comments;
+                }
+            }
+            PICTURE if mode == TagMode::Replace || strip_covers => {}
+            PICTURE
+                if cover.is_some()
+                    && data.starts_with(&FRONT_COVER.to_be_bytes()) => {}
+            // The padding is recalculated.
+            PADDING => {}
+            _ => blocks.push((block_type, data)),
+        }
+    }
+
+    if let Some(metadata) = tags.metadata {
+        let artists = metadata.artists.iter().map(|(name, _)| name.as_str());
+        set_comment(&mut comments, "TITLE", [metadata.music_name.as_str()]);
+        set_comment(&mut comments, "ARTIST", artists);
+        set_comment(&mut comments, "ALBUM", [metadata.album_name.as_str()]);
+    }
+    if let Some(key) = tags.key {
+        // The Netease client stores it in DESCRIPTION for FLAC.
+        let key = String::from_utf8_lossy(key);
+        set_comment(&mut comments, "DESCRIPTION", [key.as_ref()]);
+    }
+    let vendor = vendor
+        .unwrap_or_else(|| concat!("ncm_dumper ", env!("CARGO_PKG_VERSION")).into());
+    // Right after STREAMINFO, where most readers look for it.
+    blocks.insert(1, (VORBIS_COMMENT, vorbis_comment(&vendor, &comments)));
+    if let Some(cover) = cover {
+        blocks.push((PICTURE, picture(cover)));
+    }
+
+    let blocks_len: u64 = blocks.iter().map(|(_, data)| 4 + data.len() as u64).sum();
+    let space = audio_offset - flac_offset - 4;
+    // The padding is too large for a block if a large cover is removed, then
+    // it's better to rewrite the file and free the space anyway.
+    let padding = (blocks_len + 4 <= space).then(|| space - blocks_len - 4);
+    if blocks_len == space || padding.is_some_and(|len| len < 1 << 24) {
+        // Fit the blocks into the space of the old ones.
+        let head = encode_flac_blocks(&blocks, padding)?;
+        file.seek(SeekFrom::Start(flac_offset))?;
+        return file.write_all(&head);
+    }
+
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let result = File::create(&tmp_path).and_then(|mut tmp| {
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut (&mut file).take(flac_offset), &mut tmp)?;
+        tmp.write_all(&encode_flac_blocks(&blocks, Some(DEFAULT_PADDING))?)?;
+        file.seek(SeekFrom::Start(audio_offset))?;
+        io::copy(&mut file, &mut tmp)?;
+        drop(file);
+        drop(tmp);
+        std::fs::rename(&tmp_path, path)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// The MIME type of the cover image, which is mostly in JPEG format.
+fn cover_mime_type(cover: &[u8]) -> &'static str {
+    ImageFormat::from_header(cover).map_or("image/jpeg", |format| format.mime_type())
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Read the metadata blocks of the FLAC file, along with the offset of
+/// `fLaC` after the ID3v2 tag if any, and the offset of the audio frames.
+fn read_flac_blocks(file: &mut File) -> io::Result<(Vec<Block>, u64, u64)> {
+    let mut id3_header = Vec::new();
+    (&mut *file).take(10).read_to_end(&mut id3_header)?;
+    let flac_offset = AudioFormat::id3_len(&id3_header).unwrap_or(0) as u64;
+    file.seek(SeekFrom::Start(flac_offset))?;
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    if magic != *b"fLaC" {
+        return Err(invalid_data("Not a FLAC file."));
+    }
+
+    let mut offset = flac_offset + 4;
+    let mut blocks = Vec::new();
+    loop {
+        let mut header = [0; 4];
+        file.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        let mut data = vec![0; len as usize];
+        file.read_exact(&mut data)?;
+        blocks.push((header[0] & 0x7f, data));
+        offset += 4 + len as u64;
+        // The last-metadata-block flag.
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+
+    if blocks[0].0 != STREAMINFO {
+        return Err(invalid_data("STREAMINFO is not the first metadata block."));
+    }
+    Ok((blocks, flac_offset, offset))
+}
+
+/// Encode the metadata blocks with the `fLaC` marker in front, and a padding
+/// block of `padding` bytes at the end.
+fn encode_flac_blocks(
+    blocks: &[Block],
+    padding: Option<u64>,
+) -> io::Result<Vec<u8>> {
+    let padding = padding.map(|len| (PADDING, vec![0; len as usize]));
+    let blocks: Vec<_> = blocks.iter().chain(padding.as_ref()).collect();
+
+    let mut head = b"fLaC".to_vec();
+    for (i, (block_type, data)) in blocks.iter().enumerate() {
+        // The length is stored in 24 bits.
+        let len = u32::try_from(data.len())
+            .ok()
+            .filter(|len| *len < 1 << 24)
+            .ok_or_else(|| invalid_data("The metadata block is too large."))?;
+        let last = if i == blocks.len() - 1 { 0x80 } else { 0 };
+        head.push(last | block_type);
+        head.extend_from_slice(&len.to_be_bytes()[1..]);
+        head.extend_from_slice(data);
+    }
+    Ok(head)
+}
+
+/// Split a length-prefixed field off the front of `data`.
+fn take_field<'a>(data: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let Some((len, rest)) = data.split_first_chunk::<4>() else {
+        return Err(invalid_data("The VORBIS_COMMENT block is truncated."));
+    };
+    let len = u32::from_le_bytes(*len) as usize;
+    if rest.len() < len {
+        return Err(invalid_data("The VORBIS_COMMENT block is truncated."));
+    }
+    let (field, rest) = rest.split_at(len);
+    *data = rest;
+    Ok(field)
+}
+
+/// Parse the VORBIS_COMMENT block into the vendor string and the comments
+/// in the form of `KEY=value`.
+fn parse_vorbis_comment(mut data: &[u8]) -> io::Result<(Vec<u8>, Vec<Vec<u8>>)> {
+    let vendor = take_field(&mut data)?.to_vec();
+    let Some((count, mut data)) = data.split_first_chunk::<4>() else {
+        return Err(invalid_data("The VORBIS_COMMENT block is truncated."));
+    };
+    let comments = (0..u32::from_le_bytes(*count))
+        .map(|_| take_field(&mut data).map(<[u8]>::to_vec))
+        .collect::<io::Result<_>>()?;
+    Ok((vendor, comments))
+}
+
+fn vorbis_comment(vendor: &[u8], comments: &[Vec<u8>]) -> Vec<u8> {
+    // Unlike the rest of FLAC, the lengths are little-endian.
+    let mut data = Vec::new();
+    data.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    data.extend_from_slice(vendor);
+    data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        data.extend_from_slice(comment);
+    }
+    data
+}
+
+/// Replace the comments of `key` with `values`, one comment per value.
+/// Empty values are skipped, and nothing is replaced if all are empty.
+fn set_comment<'a>(
+    comments: &mut Vec<Vec<u8>>,
+    key: &str,
+    values: impl IntoIterator<Item = &'a str>,
+) {
+    let values: Vec<_> = values
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .collect();
+    if values.is_empty() {
+        return;
+    }
+    // The keys are case-insensitive.
+    comments.retain(|comment| {
+        let comment_key = comment.split(|x| *x == b'=').next().unwrap_or_default();
+        !comment_key.eq_ignore_ascii_case(key.as_bytes())
+    });
+    comments.extend(
+        values
+            .into_iter()
+            .map(|value| format!("{key}={value}").into_bytes()),
+    );
+}
+
+/// Build the PICTURE block of the front cover.
+fn picture(cover: &[u8]) -> Vec<u8> {
+    let mime_type = cover_mime_type(cover);
+    let mut data = Vec::new();
+    data.extend_from_slice(&FRONT_COVER.to_be_bytes());
+    data.extend_from_slice(&(mime_type.len() as u32).to_be_bytes());
+    data.extend_from_slice(mime_type.as_bytes());
+    // No description.
+    data.extend_from_slice(&0u32.to_be_bytes());
+    // The width, height, color depth and number of colors, which may be 0
+    // if unknown.
+    data.extend_from_slice(&[0; 16]);
+    data.extend_from_slice(&(cover.len() as u32).to_be_bytes());
+    data.extend_from_slice(cover);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const AUDIO: &[u8] = b"\xff\xf8 audio frames";
+    const MP3_AUDIO: &[u8] = b"\xff\xfb audio frames";
+    const JPEG: &[u8] = b"\xff\xd8\xff\xe0 not a real jpeg";
+
+    /// Write a FLAC file of `blocks` and [`AUDIO`] in the temporary directory.
+    fn flac(name: &str, blocks: &[Block], padding: Option<u64>) -> PathBuf {
+        let name = format!("ncm_dumper_{}_{name}.flac", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let mut data = encode_flac_blocks(blocks, padding).unwrap();
+        data.extend_from_slice(AUDIO);
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    /// Write an MP3 file of `tag` and [`MP3_AUDIO`] in the temporary
+    /// directory.
+    fn mp3(name: &str, tag: Option<&Tag>) -> PathBuf {
+        let name = format!("ncm_dumper_{}_{name}.mp3", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, MP3_AUDIO).unwrap();
+        if let Some(tag) = tag {
+            tag.write_to_path(&path, Version::Id3v24).unwrap();
+        }
+        path
+    }
+
+    /// Read the tag back and remove the file, checking that there is only one
+    /// tag followed by the untouched audio frames.
+    fn read_id3(path: &Path) -> Tag {
+        let tag = Tag::read_from_path(path).unwrap();
+        let data = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(&data[..3], b"ID3");
+        // The size excluding the header is a syncsafe integer.
+        let size = data[6..10]
+            .iter()
+            .fold(0, |size, x| size << 7 | *x as usize);
+        assert_eq!(&data[10 + size..], MP3_AUDIO);
+        tag
+    }
+
+    /// Read the blocks back and remove the file, checking that the audio
+    /// frames are untouched and no temporary file is left.
+    fn read_back(path: &Path) -> Vec<Block> {
+        let mut file = File::open(path).unwrap();
+        let (blocks, _, _) = read_flac_blocks(&mut file).unwrap();
+        let mut audio = Vec::new();
+        file.read_to_end(&mut audio).unwrap();
+        assert_eq!(audio, AUDIO);
+        std::fs::remove_file(path).unwrap();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        assert!(!Path::new(&tmp_path).exists());
+        blocks
+    }
+
+    fn streaminfo() -> Block {
+        (STREAMINFO, vec![0; 34])
+    }
+
+    fn comment_block(vendor: &str, comments: &[&str]) -> Block {
+        let comments: Vec<_> =
+            comments.iter().map(|x| x.as_bytes().to_vec()).collect();
+        (VORBIS_COMMENT, vorbis_comment(vendor.as_bytes(), &comments))
+    }
+
+    fn picture_block(picture_type: u32) -> Block {
+        let mut data = picture(JPEG);
+        data[..4].copy_from_slice(&picture_type.to_be_bytes());
+        (PICTURE, data)
+    }
+
+    /// The vendor string and the comments of the only VORBIS_COMMENT block.
+    fn read_comments(blocks: &[Block]) -> (String, Vec<String>) {
+        let mut comments = blocks.iter().filter(|(x, _)| *x == VORBIS_COMMENT);
+        let (vendor, comments) =
+            parse_vorbis_comment(&comments.next().unwrap().1).unwrap();
+        let comments = comments.into_iter().map(|x| String::from_utf8(x).unwrap());
+        (String::from_utf8(vendor).unwrap(), comments.collect())
+    }
+
+    /// The types of the PICTURE blocks.
+    fn pictures(blocks: &[Block]) -> Vec<u32> {
+        blocks
+            .iter()
+            .filter(|(x, _)| *x == PICTURE)
+            .map(|(_, data)| u32::from_be_bytes(data[..4].try_into().unwrap()))
+            .collect()
+    }
+
+    fn metadata() -> NCMMetadata {
+        serde_json::from_str(r#"{"musicId":"1","musicName":"title","artist":[["a",1],["b",2]],"albumId":3,"album":"album","albumPicDocId":4,"albumPic":"","bitrate":320000,"duration":1000,"alias":[],"transNames":[],"format":"flac"}"#).unwrap()
+    }
+
+    const NO_TAGS: Tags = Tags {
+        metadata: None,
+        cover: None,
+        key: None,
+    };
+
+    #[test]
+    fn exact_fit() {
+        let old = [
+            streaminfo(),
+            comment_block("v", &["DESCRIPTION=163 key(old)"]),
+        ];
+        let path = flac("exact_fit", &old, None);
+        let len = std::fs::metadata(&path).unwrap().len();
+        let tags = Tags {
+            key: Some(b"163 key(new)"),
+            ..NO_TAGS
+        };
+        write_flac(&path, TagMode::Replace, false, &tags).unwrap();
+
+        // Same length without any padding, so it's written in place.
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        let blocks = read_back(&path);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(read_comments(&blocks).1, ["DESCRIPTION=163 key(new)"]);
+    }
+
+    #[test]
+    fn fit_into_padding() {
+        let path = flac("fit_into_padding", &[streaminfo()], Some(1000));
+        let len = std::fs::metadata(&path).unwrap().len();
+        let metadata = metadata();
+        let tags = Tags {
+            metadata: Some(&metadata),
+            ..NO_TAGS
+        };
+        write_flac(&path, TagMode::Merge, false, &tags).unwrap();
+
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        let blocks = read_back(&path);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[1].0, VORBIS_COMMENT);
+        assert_eq!(blocks[2].0, PADDING);
+        let (_, comments) = read_comments(&blocks);
+        assert_eq!(
+            comments,
+            ["TITLE=title", "ARTIST=a", "ARTIST=b", "ALBUM=album"]
+        );
+    }
+
+    #[test]
+    fn grow_via_temp_file() {
+        let path = flac("grow_via_temp_file", &[streaminfo()], None);
+        let tags = Tags {
+            cover: Some(JPEG),
+            ..NO_TAGS
+        };
+        write_flac(&path, TagMode::Merge, false, &tags).unwrap();
+
+        let blocks = read_back(&path);
+        assert_eq!(pictures(&blocks), [FRONT_COVER]);
+        assert_eq!(
+            blocks.last().unwrap(),
+            &(PADDING, vec![0; DEFAULT_PADDING as usize])
+        );
+    }
+
+    #[test]
+    fn no_room_for_padding() {
+        // 2 bytes shorter, which can't hold the header of a padding block.
+        let old = [
+            streaminfo(),
+            comment_block("v", &["DESCRIPTION=163 key(old)"]),
+        ];
+        let path = flac("no_room_for_padding", &old, None);
+        let tags = Tags {
+            key: Some(b"163 key(o)"),
+            ..NO_TAGS
+        };
+        write_flac(&path, TagMode::Replace, false, &tags).unwrap();
+
+        let blocks = read_back(&path);
+        assert_eq!(read_comments(&blocks).1, ["DESCRIPTION=163 key(o)"]);
+        assert_eq!(
+            blocks.last().unwrap(),
+            &(PADDING, vec![0; DEFAULT_PADDING as usize])
+        );
+    }
+
+    #[test]
+    fn merge_and_replace() {
+        let old = [
+            streaminfo(),
+            comment_block("vendor", &["title=old", "TITLE=old", "GENRE=pop"]),
+            picture_block(4),
+        ];
+        let metadata = metadata();
+        let tags = Tags {
+            metadata: Some(&metadata),
+            ..NO_TAGS
+        };
+
+        let path = flac("merge", &old, Some(100));
+        write_flac(&path, TagMode::Merge, false, &tags).unwrap();
+        let blocks = read_back(&path);
+        let (vendor, comments) = read_comments(&blocks);
+        assert_eq!(vendor, "vendor");
+        assert_eq!(
+            comments,
+            [
+                "GENRE=pop",
+                "TITLE=title",
+                "ARTIST=a",
+                "ARTIST=b",
+                "ALBUM=album"
+            ]
+        );
+        assert_eq!(pictures(&blocks), [4]);
+
+        let path = flac("replace", &old, Some(100));
+        write_flac(&path, TagMode::Replace, false, &tags).unwrap();
+        let blocks = read_back(&path);
+        let (vendor, comments) = read_comments(&blocks);
+        assert_eq!(vendor, "vendor");
+        assert_eq!(
+            comments,
+            ["TITLE=title", "ARTIST=a", "ARTIST=b", "ALBUM=album"]
+        );
+        assert!(pictures(&blocks).is_empty());
+    }
+
+    #[test]
+    fn strip_covers() {
+        let old = [streaminfo(), picture_block(FRONT_COVER), picture_block(4)];
+
+        // The new front cover replaces the old one only.
+        let path = flac("new_cover", &old, Some(100));
+        let tags = Tags {
+            cover: Some(JPEG),
+            ..NO_TAGS
+        };
+        write_flac(&path, TagMode::Merge, false, &tags).unwrap();
+        assert_eq!(pictures(&read_back(&path)), [4, FRONT_COVER]);
+
+        let path = flac("strip_covers", &old, Some(100));
+        write_flac(&path, TagMode::Merge, true, &NO_TAGS).unwrap();
+        assert!(pictures(&read_back(&path)).is_empty());
+
+        let path = flac("strip_covers_new_cover", &old, Some(100));
+        write_flac(&path, TagMode::Merge, true, &tags).unwrap();
+        assert_eq!(pictures(&read_back(&path)), [FRONT_COVER]);
+    }
+
+    #[test]
+    fn write_id3_tags() {
+        let metadata = metadata();
+        let tags = Tags {
+            metadata: Some(&metadata),
+            cover: Some(JPEG),
+            key: Some(b"163 key(new)"),
+        };
+        let path = mp3("id3", None);
+        write_id3(&path, TagMode::Merge, false, &tags).unwrap();
+
+        let tag = read_id3(&path);
+        assert_eq!(tag.version(), Version::Id3v24);
+        assert_eq!(tag.title(), Some("title"));
+        assert_eq!(tag.artists(), Some(vec!["a", "b"]));
+        assert_eq!(tag.album(), Some("album"));
+        assert_eq!(tag.duration(), Some(1000));
+        let pictures: Vec<_> = tag.pictures().collect();
+        assert_eq!(pictures.len(), 1);
+        assert_eq!(pictures[0].picture_type, PictureType::CoverFront);
+        assert_eq!(pictures[0].mime_type, "image/jpeg");
+        assert_eq!(pictures[0].data, JPEG);
+        let comments: Vec<_> = tag.comments().map(|x| x.text.as_str()).collect();
+        assert_eq!(comments, ["163 key(new)"]);
+    }
+
+    #[test]
+    fn replace_existing_id3() {
+        let mut old = Tag::new();
+        old.set_title("old");
+        old.set_genre("pop");
+        let metadata = metadata();
+        let tags = Tags {
+            metadata: Some(&metadata),
+            ..NO_TAGS
+        };
+
+        // The existing tag is updated instead of another one being added.
+        let path = mp3("id3_merge", Some(&old));
+        write_id3(&path, TagMode::Merge, false, &tags).unwrap();
+        let tag = read_id3(&path);
+        assert_eq!(tag.title(), Some("title"));
+        assert_eq!(tag.genre(), Some("pop"));
+
+        let path = mp3("id3_replace", Some(&old));
+        write_id3(&path, TagMode::Replace, false, &tags).unwrap();
+        let tag = read_id3(&path);
+        assert_eq!(tag.title(), Some("title"));
+        assert_eq!(tag.genre(), None);
+    }
+
+    #[test]
+    fn remove_large_cover() {
+        // The largest PICTURE block, so that the padding left in its place
+        // can't be stored in a block.
+        let overhead = picture(JPEG).len() - JPEG.len();
+        let mut cover = JPEG.to_vec();
+        cover.resize((1 << 24) - 1 - overhead, 0);
+        let old = [streaminfo(), (PICTURE, picture(&cover))];
+        let path = flac("remove_large_cover", &old, Some(100));
+        let metadata = metadata();
+        let tags = Tags {
+            metadata: Some(&metadata),
+            ..NO_TAGS
+        };
+        write_flac(&path, TagMode::Merge, true, &tags).unwrap();
+
+        let blocks = read_back(&path);
+        assert!(pictures(&blocks).is_empty());
+        assert_eq!(
+            blocks.last().unwrap(),
+            &(PADDING, vec![0; DEFAULT_PADDING as usize])
+        );
+    }
+
+    #[test]
+    fn keep_id3_in_front() {
+        let id3 = b"ID3\x04\0\0\0\0\0\x06tagged";
+        let metadata = metadata();
+        let tags = Tags {
+            metadata: Some(&metadata),
+            ..NO_TAGS
+        };
+        // Written in place, then by rewriting the file.
+        for (name, padding) in [("id3_flac", Some(1000)), ("id3_flac_grow", None)] {
+            let path = flac(name, &[streaminfo()], padding);
+            let mut data = id3.to_vec();
+            data.extend(std::fs::read(&path).unwrap());
+            std::fs::write(&path, data).unwrap();
+            write_flac(&path, TagMode::Merge, false, &tags).unwrap();
+
+            assert!(std::fs::read(&path).unwrap().starts_with(id3));
+            let blocks = read_back(&path);
+            let (_, comments) = read_comments(&blocks);
+            assert_eq!(
+                comments,
+                ["TITLE=title", "ARTIST=a", "ARTIST=b", "ALBUM=album"]
+            );
+        }
+    }
+}